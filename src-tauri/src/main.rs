@@ -9,13 +9,12 @@ use tauri::{Emitter, AppHandle};
 use serde::{Deserialize, Serialize};
 use maxminddb::Reader;
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::path::Path;
 use directories::BaseDirs;
 use sysinfo::{System, SystemExt, ProcessExt, PidExt};
 use tokio::process::Command;
-use tokio::io::{BufReader, AsyncBufReadExt};
 use tokio::sync::Notify;
 use tracing_subscriber::{self, Layer};
 use once_cell::sync::Lazy;
@@ -27,7 +26,12 @@ use tokio::fs;
 #[tauri::command]
 async fn geo_lookup(ip: String) -> Result<GeoResult, String> {
     // Check if it's a private IP - don't look up geolocation for private IPs
-    if ip.starts_with("10.") || 
+    let is_private_v6 = matches!(
+        ip.parse::<std::net::IpAddr>(),
+        Ok(std::net::IpAddr::V6(v6)) if is_private_ipv6(&v6)
+    );
+    if is_private_v6 ||
+       ip.starts_with("10.") || 
        ip.starts_with("192.168.") || 
        (ip.starts_with("172.") && {
            let parts: Vec<&str> = ip.split('.').collect();
@@ -45,16 +49,17 @@ async fn geo_lookup(ip: String) -> Result<GeoResult, String> {
             city: Some("Private/Internal".to_string()),
             country: None,
             country_code: None,
+            flag_emoji: None,
         });
     }
 
-    let db = GEO_DB.as_ref().ok_or_else(|| "Geolocation database not loaded".to_string())?;
+    let db = current_geo_db().ok_or_else(|| "Geolocation database not loaded".to_string())?;
     let addr: std::net::IpAddr = ip.parse().map_err(|_| "Invalid IP address".to_string())?;
 
     // Direct lookup without deserializing to our custom struct
     use maxminddb::PathElement;
 
-    let result = db.lookup(addr).map_err(|e| e.to_string())?;
+    let result = db.reader.lookup(addr).map_err(|e| e.to_string())?;
 
     let lat: Option<f64> = result.decode_path(&[
         PathElement::Key("location"),
@@ -89,6 +94,7 @@ async fn geo_lookup(ip: String) -> Result<GeoResult, String> {
         lng,
         city: city_name,
         country: country_name,
+        flag_emoji: country_code.as_deref().and_then(flag_emoji),
         country_code,
     })
 }
@@ -100,32 +106,310 @@ pub struct GeoLocation {
     pub city: Option<String>,
     pub country: Option<String>,
     pub country_code: Option<String>,
+    // Flag of country_code, e.g. "🇺🇸" for "US"
+    #[serde(default)]
+    pub flag_emoji: Option<String>,
 }
 
-static GEO_DB: Lazy<Option<Reader<Vec<u8>>>> = Lazy::new(|| {
+struct LoadedGeoDb {
+    path: std::path::PathBuf,
+    reader: Reader<Vec<u8>>,
+}
+
+const GEOLITE_DB_URL: &str = "https://github.com/P3TERX/GeoLite.mmdb/raw/download/GeoLite2-City.mmdb";
+// Last commit touching the database on the download branch, used when the raw file is served
+// without Last-Modified (raw.githubusercontent.com only sends an ETag)
+const GEOLITE_COMMITS_URL: &str =
+    "https://api.github.com/repos/P3TERX/GeoLite.mmdb/commits?sha=download&path=GeoLite2-City.mmdb&per_page=1";
+
+// Swappable so set_geo_db_path can replace the database without a restart.
+// Filled in by init_geo_db at startup, lookups made before that see no database.
+static GEO_DB: RwLock<Option<Arc<LoadedGeoDb>>> = RwLock::new(None);
+static GEO_DB_STATUS: RwLock<GeoDbLoadStatus> = RwLock::new(GeoDbLoadStatus::Pending);
+
+// How long startup waits for the database before carrying on without it
+const GEO_DB_LOAD_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum GeoDbLoadStatus {
+    Pending,
+    Loaded,
+    NotFound,
+    // Still reading in the background, becomes Loaded (or NotFound/Failed) when it finishes
+    TimedOut,
+    Failed,
+}
+
+fn set_geo_db_status(status: GeoDbLoadStatus) {
+    *GEO_DB_STATUS.write().expect("Failed to acquire geo database status lock") = status;
+}
+
+fn current_geo_db() -> Option<Arc<LoadedGeoDb>> {
+    GEO_DB.read().expect("Failed to acquire geo database lock").clone()
+}
+
+fn load_geo_db() -> Option<Arc<LoadedGeoDb>> {
     // This matches your app_data_dir logic: %APPDATA%\TraceRT
     let app_data_dir = BaseDirs::new()
         .map(|dirs| dirs.data_dir().join("TraceRT"))
         .unwrap_or_else(|| std::path::PathBuf::from("."));
 
-    let possible_paths = [
-        // if you ship it as a resource (optional)
-        app_data_dir.join("resources").join("GeoLite2-City.mmdb"),
-        // main location you want
-        app_data_dir.join("GeoLite2-City.mmdb"),
-    ];
+    let possible_paths = geo_db_candidates(&app_data_dir, load_settings().geo_db_path);
 
     for path in &possible_paths {
         if path.exists() {
             match Reader::open_readfile(path) {
-                Ok(reader) => return Some(reader),
+                Ok(reader) => return Some(Arc::new(LoadedGeoDb { path: path.clone(), reader })),
                 Err(e) => eprintln!("Failed to load geodb from {:?}: {}", path, e),
             }
         }
     }
 
     None
-});
+}
+
+// Places the database is looked for, in order
+fn geo_db_candidates(app_data_dir: &Path, configured: Option<String>) -> Vec<std::path::PathBuf> {
+    let mut possible_paths = Vec::new();
+    // a database the user pinned explicitly always wins
+    if let Some(configured) = configured {
+        possible_paths.push(std::path::PathBuf::from(configured));
+    }
+    // if you ship it as a resource (optional)
+    possible_paths.push(app_data_dir.join("resources").join("GeoLite2-City.mmdb"));
+    // main location you want
+    possible_paths.push(app_data_dir.join("GeoLite2-City.mmdb"));
+    possible_paths
+}
+
+// Serializes loads so preload_geo_db and the startup load don't read the file twice
+static GEO_DB_INIT: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+// Set while a load that timed out is still reading in the background
+static GEO_DB_LATE_LOAD: AtomicBool = AtomicBool::new(false);
+
+// Wait up to `timeout` for a blocking task. If it takes longer it keeps running and
+// `on_late` gets its result when it finishes, None is returned right away.
+async fn finish_in_background_after<T, F>(
+    timeout: std::time::Duration,
+    mut handle: tokio::task::JoinHandle<T>,
+    on_late: F,
+) -> Option<Result<T, tokio::task::JoinError>>
+where
+    T: Send + 'static,
+    F: FnOnce(Result<T, tokio::task::JoinError>) + Send + 'static,
+{
+    match tokio::time::timeout(timeout, &mut handle).await {
+        Ok(result) => Some(result),
+        Err(_) => {
+            tokio::spawn(async move { on_late(handle.await) });
+            None
+        }
+    }
+}
+
+// A load that outlived GEO_DB_LOAD_TIMEOUT finished, install its database unless one was set meanwhile
+fn install_late_geo_db(result: Result<Option<Arc<LoadedGeoDb>>, tokio::task::JoinError>) {
+    if let Some(status) = install_late_load(&GEO_DB, result) {
+        set_geo_db_status(status);
+    }
+    GEO_DB_LATE_LOAD.store(false, Ordering::SeqCst);
+}
+
+// The status to report for a late load, None when set_geo_db_path installed a database while
+// it was reading. That database and its status stay, whatever the late load found.
+fn install_late_load<T>(
+    slot: &RwLock<Option<T>>,
+    result: Result<Option<T>, tokio::task::JoinError>,
+) -> Option<GeoDbLoadStatus> {
+    let mut current = slot.write().expect("Failed to acquire geo database lock");
+    if current.is_some() {
+        tracing::info!("[Rust] [GEO] Ignoring late geolocation database load, a database was set meanwhile");
+        return None;
+    }
+    match result {
+        Ok(Some(db)) => {
+            tracing::info!("[Rust] [GEO] Geolocation database loaded late");
+            *current = Some(db);
+            Some(GeoDbLoadStatus::Loaded)
+        }
+        Ok(None) => {
+            tracing::info!("[Rust] [GEO] No geolocation database found");
+            Some(GeoDbLoadStatus::NotFound)
+        }
+        Err(e) => {
+            tracing::error!("[Rust] [GEO] Geolocation database load failed: {}", e);
+            Some(GeoDbLoadStatus::Failed)
+        }
+    }
+}
+
+// Load the database off the async runtime so a slow (network) drive can't stall startup
+async fn init_geo_db() -> GeoDbLoadStatus {
+    let _init = GEO_DB_INIT.lock().await;
+    if current_geo_db().is_some() {
+        return GeoDbLoadStatus::Loaded;
+    }
+    // The last load is still reading, a second one would only read the same file again
+    if GEO_DB_LATE_LOAD.load(Ordering::SeqCst) {
+        return GeoDbLoadStatus::TimedOut;
+    }
+
+    let load = tokio::task::spawn_blocking(load_geo_db);
+    GEO_DB_LATE_LOAD.store(true, Ordering::SeqCst);
+    let status = match finish_in_background_after(GEO_DB_LOAD_TIMEOUT, load, install_late_geo_db).await {
+        Some(Ok(Some(db))) => {
+            tracing::info!("[Rust] [GEO] Geolocation database loaded from {:?}", db.path);
+            *GEO_DB.write().expect("Failed to acquire geo database lock") = Some(db);
+            GeoDbLoadStatus::Loaded
+        }
+        Some(Ok(None)) => {
+            tracing::info!("[Rust] [GEO] No geolocation database found");
+            GeoDbLoadStatus::NotFound
+        }
+        Some(Err(e)) => {
+            tracing::error!("[Rust] [GEO] Geolocation database load failed: {}", e);
+            GeoDbLoadStatus::Failed
+        }
+        None => {
+            // Lookups go without the database until the read finishes, it is installed then
+            tracing::warn!(
+                "[Rust] [GEO] Geolocation database load took longer than {:?}, continuing without it",
+                GEO_DB_LOAD_TIMEOUT
+            );
+            set_geo_db_status(GeoDbLoadStatus::TimedOut);
+            return GeoDbLoadStatus::TimedOut;
+        }
+    };
+    GEO_DB_LATE_LOAD.store(false, Ordering::SeqCst);
+    set_geo_db_status(status);
+    status
+}
+
+// Let the frontend make sure the database is loaded before the first trace, e.g. at launch
+// or after downloading it. Loads it if the startup load found nothing or gave up.
+#[tauri::command]
+async fn preload_geo_db() -> Result<(), String> {
+    match init_geo_db().await {
+        GeoDbLoadStatus::Loaded => Ok(()),
+        GeoDbLoadStatus::NotFound => Err("Geolocation database not found".to_string()),
+        GeoDbLoadStatus::TimedOut => Err(format!(
+            "Geolocation database took longer than {:?} to load",
+            GEO_DB_LOAD_TIMEOUT
+        )),
+        GeoDbLoadStatus::Failed | GeoDbLoadStatus::Pending => Err("Failed to load geolocation database".to_string()),
+    }
+}
+
+// Persisted user settings, stored as settings.json in the app data directory
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct AppSettings {
+    #[serde(default)]
+    geo_db_path: Option<String>,
+    // Online geolocation endpoint used by the fallback, "{ip}" is replaced with the address
+    #[serde(default)]
+    geo_fallback_url: Option<String>,
+    // TraceOptions of the last trace setup, kept as plain JSON so a bad entry only loses the
+    // options and not the whole settings file
+    #[serde(default)]
+    last_options: Option<serde_json::Value>,
+}
+
+fn settings_path() -> std::path::PathBuf {
+    BaseDirs::new()
+        .map(|dirs| dirs.data_dir().join("TraceRT"))
+        .unwrap_or_else(|| std::path::PathBuf::from("."))
+        .join("settings.json")
+}
+
+fn load_settings() -> AppSettings {
+    load_settings_from(&settings_path())
+}
+
+fn load_settings_from(path: &Path) -> AppSettings {
+    match std::fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+            tracing::warn!("[Rust] [SETTINGS] Ignoring unreadable settings file {:?}: {}", path, e);
+            AppSettings::default()
+        }),
+        Err(_) => AppSettings::default(),
+    }
+}
+
+fn save_settings(settings: &AppSettings) -> Result<(), String> {
+    save_settings_to(&settings_path(), settings)
+}
+
+fn save_settings_to(path: &Path, settings: &AppSettings) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create settings directory: {}", e))?;
+    }
+    let content = serde_json::to_string_pretty(settings)
+        .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+    std::fs::write(path, content)
+        .map_err(|e| format!("Failed to save settings: {}", e))
+}
+
+#[tauri::command]
+async fn set_geo_db_path(path: String) -> Result<(), String> {
+    let db_path = std::path::PathBuf::from(&path);
+    if !db_path.is_file() {
+        return Err(format!("Geolocation database not found: {}", path));
+    }
+
+    // Make sure it really is a MaxMind database before swapping it in
+    let open_path = db_path.clone();
+    let reader = tokio::task::spawn_blocking(move || Reader::open_readfile(&open_path))
+        .await
+        .map_err(|e| format!("Failed to load geolocation database: {}", e))?
+        .map_err(|e| format!("Not a valid geolocation database: {}", e))?;
+
+    let mut settings = load_settings();
+    settings.geo_db_path = Some(path.clone());
+    save_settings(&settings)?;
+
+    *GEO_DB.write().expect("Failed to acquire geo database lock") = Some(Arc::new(LoadedGeoDb {
+        path: db_path,
+        reader,
+    }));
+    set_geo_db_status(GeoDbLoadStatus::Loaded);
+    tracing::info!("[Rust] [GEO] Geolocation database switched to {}", path);
+    Ok(())
+}
+
+#[tauri::command]
+fn save_last_options(options: TraceOptions) -> Result<(), String> {
+    let mut settings = load_settings();
+    settings.last_options = Some(serde_json::to_value(&options)
+        .map_err(|e| format!("Failed to serialize trace options: {}", e))?);
+    save_settings(&settings)
+}
+
+// Options saved by save_last_options, or the defaults when none were saved or they no longer hold
+#[tauri::command]
+fn load_last_options() -> TraceOptions {
+    last_options_or_default(load_settings().last_options)
+}
+
+fn last_options_or_default(saved: Option<serde_json::Value>) -> TraceOptions {
+    let saved = match saved {
+        Some(saved) => saved,
+        None => return TraceOptions::default(),
+    };
+    parse_saved_options(saved).unwrap_or_else(|e| {
+        tracing::warn!("[Rust] [SETTINGS] Ignoring saved trace options: {}", e);
+        TraceOptions::default()
+    })
+}
+
+fn parse_saved_options(saved: serde_json::Value) -> Result<TraceOptions, String> {
+    let options: TraceOptions = serde_json::from_value(saved)
+        .map_err(|e| format!("Invalid trace options: {}", e))?;
+    // Run the same checks a trace would, a host name target leaves the family unconstrained
+    prepare_trace_command("localhost", &options)?;
+    Ok(options)
+}
 #[derive(Serialize, Clone)]
 struct TraceLineEvent {
   trace_id: String,
@@ -137,24 +421,284 @@ struct TraceLineEvent {
 struct TraceCompleteEvent {
   trace_id: String,
   result: TraceResult,
+  summary: TraceSummary,
+}
+
+// Figures derived from a finished trace, sent along with trace:complete
+#[derive(Serialize, Clone)]
+struct TraceSummary {
+  routing: Option<RoutingEfficiency>,
+  // Distinct /24 (IPv4) and /48 (IPv6) networks the path went through, in path order
+  unique_networks: Vec<String>,
+}
+
+fn trace_summary(result: &TraceResult) -> TraceSummary {
+  TraceSummary {
+    routing: routing_efficiency(result),
+    unique_networks: unique_networks(result),
+  }
+}
+
+// Every address seen on the path, including the other routers of load-balanced hops,
+// truncated to its /24 or /48
+fn unique_networks(result: &TraceResult) -> Vec<String> {
+  let mut networks: Vec<String> = Vec::new();
+  let addresses = result.hops.iter()
+    .flat_map(|hop| hop.ip.iter().chain(hop.probe_ips.iter().flatten()));
+
+  for address in addresses {
+    let network = match address.parse::<std::net::IpAddr>() {
+      Ok(std::net::IpAddr::V4(v4)) => {
+        let [a, b, c, _] = v4.octets();
+        format!("{}.{}.{}.0/24", a, b, c)
+      }
+      Ok(std::net::IpAddr::V6(v6)) => {
+        let segments = v6.segments();
+        let prefix = std::net::Ipv6Addr::new(segments[0], segments[1], segments[2], 0, 0, 0, 0, 0);
+        format!("{}/48", prefix)
+      }
+      Err(_) => continue,
+    };
+    if !networks.contains(&network) {
+      networks.push(network);
+    }
+  }
+
+  networks
+}
+
+// Quiet traces skip trace:line, hop:update and trace:complete still go out
+fn should_emit_line(options: &TraceOptions) -> bool {
+  !options.quiet.unwrap_or(false)
 }
 
 fn emit_trace_line(app: &AppHandle, trace_id: &str, line_no: u32, line: &str) {
   let payload = TraceLineEvent {
     trace_id: trace_id.to_string(),
     line_no,
-    line: line.to_string(),
+    line: escape_control_chars(line).into_owned(),
   };
 
   // emit to all windows (easy mode)
   let _ = app.emit("trace:line", payload);
 }
 
+// Escape control characters (other than tab) as \xNN so ANSI sequences from
+// e.g. a crafted PTR name reach the UI as visible text instead of being interpreted
+fn escape_control_chars(line: &str) -> std::borrow::Cow<'_, str> {
+  if !line.chars().any(|c| c.is_control() && c != '\t') {
+    return std::borrow::Cow::Borrowed(line);
+  }
+
+  let mut escaped = String::with_capacity(line.len() + 8);
+  for c in line.chars() {
+    // is_control() only covers C0/C1 codes, so two hex digits always suffice
+    if c.is_control() && c != '\t' {
+      escaped.push_str(&format!("\\x{:02x}", c as u32));
+    } else {
+      escaped.push(c);
+    }
+  }
+  std::borrow::Cow::Owned(escaped)
+}
+
+#[derive(Serialize, Clone)]
+struct TraceHopsInitEvent {
+  trace_id: String,
+  max_hops: u32,
+  hops: Vec<HopData>,
+}
+
+// Stand-in for a hop no line has arrived for, "pending" while the trace runs and
+// "skipped" once it finished without reaching that hop
+fn placeholder_hop(hop: u32, status: &str) -> HopData {
+  HopData {
+    hop,
+    host: None,
+    ip: None,
+    latencies: Vec::new(),
+    avg_latency: None,
+    status: status.to_string(),
+    geo: None,
+    annotation: None,
+    received_at: String::new(),
+    well_known: None,
+    probe_ips: Vec::new(),
+  }
+}
+
+fn emit_hops_init(app: &AppHandle, trace_id: &str, max_hops: u32) {
+  let hops = (1..=max_hops)
+    .map(|hop| placeholder_hop(hop, "pending"))
+    .collect();
+
+  let payload = TraceHopsInitEvent {
+    trace_id: trace_id.to_string(),
+    max_hops,
+    hops,
+  };
+
+  if let Err(e) = app.emit("trace:hops_init", payload) {
+    tracing::warn!("[Rust] [TRACE] Failed to emit 'trace:hops_init' event: {}", e);
+  }
+}
+
+#[derive(Serialize, Clone)]
+struct HopStatusEvent {
+  trace_id: String,
+  hop: u32,
+  old_status: String,
+  new_status: String,
+}
+
+// Status of a hop over every probe seen so far: "success", "partial" (some probes lost),
+// "timeout" or "unreachable". Every hop starts out "pending" from trace:hops_init, the ones the
+// trace never reached end up "skipped" (see finish_trace).
+fn aggregate_hop_status(hop_data: &HopData) -> &'static str {
+  if hop_data.status == "unreachable" {
+    return "unreachable";
+  }
+  let answered = hop_data.latencies.iter().filter(|latency| latency.is_some()).count();
+  if answered == 0 {
+    "timeout"
+  } else if answered < hop_data.latencies.len() {
+    "partial"
+  } else {
+    "success"
+  }
+}
+
+// Record the hop's aggregate status, returning (old, new) when it changed
+fn hop_status_transition(statuses: &mut HashMap<u32, String>, hop_data: &HopData) -> Option<(String, String)> {
+  let new_status = aggregate_hop_status(hop_data);
+  let old_status = statuses.insert(hop_data.hop, new_status.to_string())
+    .unwrap_or_else(|| "pending".to_string());
+  if old_status == new_status {
+    None
+  } else {
+    Some((old_status, new_status.to_string()))
+  }
+}
+
+fn emit_hop_status(app: Option<&AppHandle>, trace_id: &str, statuses: &mut HashMap<u32, String>, hop_data: &HopData) {
+  let (old_status, new_status) = match hop_status_transition(statuses, hop_data) {
+    Some(transition) => transition,
+    None => return,
+  };
+  let app = match app {
+    Some(app) => app,
+    None => return,
+  };
+
+  let payload = HopStatusEvent {
+    trace_id: trace_id.to_string(),
+    hop: hop_data.hop,
+    old_status,
+    new_status,
+  };
+
+  if let Err(e) = app.emit("hop:status", payload) {
+    tracing::warn!("[Rust] [TRACE] Failed to emit 'hop:status' event: {}", e);
+  }
+}
+
+// Number of raw lines sent along with a parse_failed warning
+const PARSE_FAILED_SAMPLE_LINES: usize = 5;
+
+// The tool ran and printed something but none of it looked like a hop, most likely
+// an output format the parser doesn't know rather than an empty path
+fn parse_failed_sample(hops: &[HopData], raw_output: &str) -> Option<Vec<String>> {
+  if !hops.is_empty() || raw_output.trim().is_empty() {
+    return None;
+  }
+  Some(
+    raw_output.lines()
+      .filter(|line| !line.trim().is_empty())
+      .take(PARSE_FAILED_SAMPLE_LINES)
+      .map(|line| line.to_string())
+      .collect(),
+  )
+}
+
+#[derive(Serialize, Clone)]
+struct TraceWarningEvent {
+  trace_id: String,
+  code: String,
+  message: String,
+  lines: Vec<String>,
+}
+
+fn emit_trace_warning(app: &AppHandle, trace_id: &str, code: &str, message: &str, lines: Vec<String>) {
+  let payload = TraceWarningEvent {
+    trace_id: trace_id.to_string(),
+    code: code.to_string(),
+    message: message.to_string(),
+    lines,
+  };
+
+  if let Err(e) = app.emit("trace:warning", payload) {
+    tracing::warn!("[Rust] [TRACE] Failed to emit 'trace:warning' event: {}", e);
+  }
+}
+
+#[derive(Serialize, Clone)]
+struct TraceHeartbeatEvent {
+  trace_id: String,
+  elapsed_ms: u64,
+  hops_so_far: usize,
+}
+
+fn emit_trace_heartbeat(app: Option<&AppHandle>, trace_id: &str, elapsed: std::time::Duration, hops_so_far: usize) {
+  let app = match app {
+    Some(app) => app,
+    None => return,
+  };
+
+  let payload = TraceHeartbeatEvent {
+    trace_id: trace_id.to_string(),
+    elapsed_ms: elapsed.as_millis() as u64,
+    hops_so_far,
+  };
+
+  if let Err(e) = app.emit("trace:heartbeat", payload) {
+    tracing::warn!("[Rust] [TRACE] Failed to emit 'trace:heartbeat' event: {}", e);
+  }
+}
+
+// Placeholders from trace:hops_init that never got a line, e.g. past the destination
+fn unreached_hops(max_hops: u32, hops: &[HopData]) -> Vec<HopData> {
+  (1..=max_hops)
+    .filter(|hop| !hops.iter().any(|seen| seen.hop == *hop))
+    .map(|hop| placeholder_hop(hop, "skipped"))
+    .collect()
+}
+
+// Final updates of a trace: every placeholder still pending is marked skipped, then trace:complete
+async fn finish_trace(app: &AppHandle, trace_id: &str, max_hops: u32, result: &TraceResult) {
+  for hop_data in unreached_hops(max_hops, &result.hops) {
+    let payload = HopStatusEvent {
+      trace_id: trace_id.to_string(),
+      hop: hop_data.hop,
+      old_status: "pending".to_string(),
+      new_status: hop_data.status.clone(),
+    };
+    if let Err(e) = app.emit("hop:status", payload) {
+      tracing::warn!("[Rust] [TRACE] Failed to emit 'hop:status' event: {}", e);
+    }
+    if let Err(e) = emit_hop_update(app.clone(), trace_id, hop_data).await {
+      tracing::warn!("[Rust] [TRACE] Failed to emit hop update: {}", e);
+    }
+  }
+
+  emit_trace_complete(app, trace_id, result);
+}
+
 fn emit_trace_complete(app: &AppHandle, trace_id: &str, result: &TraceResult) {
   tracing::info!("[Rust] [TRACE] emit_trace_complete called with trace_id: {}", trace_id);
   let payload = TraceCompleteEvent {
     trace_id: trace_id.to_string(),
     result: result.clone(),
+    summary: trace_summary(result),
   };
 
   // emit to all windows (easy mode)
@@ -246,7 +790,7 @@ fn log_error(message: String) {
     tracing::error!("[React] {}", message);
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 struct GeoResult {
     ip: String,
     lat: Option<f64>,
@@ -254,6 +798,7 @@ struct GeoResult {
     city: Option<String>,
     country: Option<String>,
     country_code: Option<String>,
+    flag_emoji: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -265,8 +810,20 @@ pub struct HopData {
     pub latencies: Vec<Option<f64>>,
     #[serde(rename = "avgLatency")]
     pub avg_latency: Option<f64>,
-    pub status: String, // "success", "timeout", "pending"
+    pub status: String, // "success", "timeout", "pending", "unreachable"
     pub geo: Option<GeoLocation>,
+    // Explanation for non-standard hop results, e.g. "Destination host unreachable (reported by 10.0.0.1)"
+    pub annotation: Option<String>,
+    // RFC3339 wall-clock time the hop line was read, set by the read loop
+    #[serde(rename = "receivedAt", default)]
+    pub received_at: String,
+    // Friendly name for well-known addresses, e.g. "Google DNS" for 8.8.8.8
+    #[serde(rename = "wellKnown", default)]
+    pub well_known: Option<String>,
+    // Address that answered each probe, parallel to latencies (None for a lost probe).
+    // Differs between probes on load-balanced (ECMP) paths.
+    #[serde(rename = "probeIps", default)]
+    pub probe_ips: Vec<Option<String>>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -275,15 +832,24 @@ pub struct TraceResult {
     #[serde(rename = "resolvedIp")]
     pub resolved_ip: Option<String>,
     pub hops: Vec<HopData>,
+    // stdout and stderr lines merged in the order they were read
     #[serde(rename = "rawOutput")]
     pub raw_output: String,
+    #[serde(rename = "stdoutRaw", default)]
+    pub stdout_raw: String,
+    #[serde(rename = "stderrRaw", default)]
+    pub stderr_raw: String,
     #[serde(rename = "startTime")]
     pub start_time: String,
     #[serde(rename = "endTime")]
     pub end_time: Option<String>,
+    #[serde(rename = "reachedDestination")]
+    pub reached_destination: bool,
+    // Why the trace failed even though the tool ran, e.g. an unreachable destination
+    pub error: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct TraceOptions {
     #[serde(rename = "maxHops")]
     pub max_hops: Option<u32>,
@@ -293,22 +859,174 @@ pub struct TraceOptions {
     pub probes_per_hop: Option<u32>,
     #[serde(rename = "resolveDns")]
     pub resolve_dns: Option<bool>,
+    pub family: Option<IpFamily>,
+    // Skip per-line trace:line events, only hop:update and trace:complete are emitted
+    pub quiet: Option<bool>,
+    // mtr-style mode: re-run the trace until stopped, emitting per-hop trace:stats after each run
+    pub continuous: Option<bool>,
+    // Pause between continuous runs
+    #[serde(rename = "intervalMs")]
+    pub interval_ms: Option<u64>,
+    // Also send one hop:settled event per hop once all of its probes are in
+    #[serde(rename = "settledEvents")]
+    pub settled_events: Option<bool>,
+    // RTT samples kept per hop for the continuous statistics
+    #[serde(rename = "statsWindow")]
+    pub stats_window: Option<usize>,
+    // Ask an online geolocation service for public hops the local database can't place (opt-in)
+    #[serde(rename = "geoFallbackOnline")]
+    pub geo_fallback_online: Option<bool>,
+    // Decimal places kept in avgLatency, sub-millisecond values keep 3 significant figures
+    #[serde(rename = "latencyPrecision")]
+    pub latency_precision: Option<u32>,
+    // Run this traceroute/tracert binary instead of looking the tool up on PATH. Must be an
+    // absolute path to a file named traceroute, traceroute6 or tracert.
+    #[serde(rename = "toolPath")]
+    pub tool_path: Option<String>,
+    // TTL to start probing at, Unix only (tracert has no equivalent)
+    #[serde(rename = "firstHop")]
+    pub first_hop: Option<u32>,
+    // Fixed source port for the probes, Linux traceroute only (not BusyBox). traceroute then sends one probe
+    // at a time (--sport implies -N 1), the destination port still increments per probe.
+    #[serde(rename = "sourcePort")]
+    pub source_port: Option<u16>,
+    // Hops of this trace enriched (reverse DNS, geolocation) at the same time
+    #[serde(rename = "enrichConcurrency")]
+    pub enrich_concurrency: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IpFamily {
+    V4,
+    V6,
+    // Pick based on the records the target resolves to
+    Auto,
 }
 
 struct RunningTrace {
     cancel_notify: Arc<Notify>,
+    // Set by stop_trace so the task can tell a cancellation apart from a failure
+    cancelled: Arc<AtomicBool>,
     handle: tokio::task::JoinHandle<Result<TraceResult, String>>,
 }
 
 struct AppState {
     running_traces: Arc<Mutex<HashMap<String, RunningTrace>>>,
+    // Trace tool processes spawned and not yet reaped
+    active_children: Arc<AtomicUsize>,
+    // Final state of recently finished traces, oldest first
+    trace_outcomes: Arc<Mutex<std::collections::VecDeque<TraceOutcome>>>,
+}
+
+// Finished traces kept for get_trace_outcome
+const MAX_TRACE_OUTCOMES: usize = 100;
+
+#[derive(Serialize, Clone)]
+struct TraceOutcome {
+    trace_id: String,
+    status: String, // "completed", "failed", "cancelled", "panicked"
+    result: Option<TraceResult>,
+    error: Option<String>,
+    finished_at: String,
+}
+
+fn record_trace_outcome(
+    trace_outcomes: &Mutex<std::collections::VecDeque<TraceOutcome>>,
+    trace_id: &str,
+    status: &str,
+    result: Option<TraceResult>,
+    error: Option<String>,
+) {
+    tracing::debug!("[Rust] [TRACE] Recording outcome '{}' for trace_id: {}", status, trace_id);
+    let mut outcomes = trace_outcomes.lock().expect("Failed to acquire mutex lock for trace outcomes");
+    outcomes.push_back(TraceOutcome {
+        trace_id: trace_id.to_string(),
+        status: status.to_string(),
+        result,
+        error,
+        finished_at: chrono::Utc::now().to_rfc3339(),
+    });
+    while outcomes.len() > MAX_TRACE_OUTCOMES {
+        outcomes.pop_front();
+    }
+}
+
+// Runs a trace to its end and stores how it ended. Cancellation is handled inside the trace so the
+// child is killed and reaped before it returns. A panic is caught here so it still ends up as an
+// observable outcome.
+async fn run_and_record_outcome<F>(
+    trace_future: F,
+    trace_outcomes: &Mutex<std::collections::VecDeque<TraceOutcome>>,
+    trace_id: &str,
+    cancelled: &AtomicBool,
+) -> Result<TraceResult, String>
+where
+    F: std::future::Future<Output = Result<TraceResult, String>>,
+{
+    use futures::FutureExt;
+    match std::panic::AssertUnwindSafe(trace_future).catch_unwind().await {
+        Ok(Ok(trace_result)) => {
+            record_trace_outcome(trace_outcomes, trace_id, "completed", Some(trace_result.clone()), None);
+            Ok(trace_result)
+        }
+        Ok(Err(e)) => {
+            let status = if cancelled.load(Ordering::SeqCst) { "cancelled" } else { "failed" };
+            record_trace_outcome(trace_outcomes, trace_id, status, None, Some(e.clone()));
+            Err(e)
+        }
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            tracing::error!("[Rust] [TRACE] Trace task panicked for trace_id: {}: {}", trace_id, message);
+            let error = format!("Trace panicked: {}", message);
+            record_trace_outcome(trace_outcomes, trace_id, "panicked", None, Some(error.clone()));
+            Err(error)
+        }
+    }
+}
+
+fn find_trace_outcome(trace_outcomes: &Mutex<std::collections::VecDeque<TraceOutcome>>, trace_id: &str) -> Option<TraceOutcome> {
+    let outcomes = trace_outcomes.lock().expect("Failed to acquire mutex lock for trace outcomes");
+    outcomes.iter().rev().find(|outcome| outcome.trace_id == trace_id).cloned()
+}
+
+// Final state of a finished trace, None while it is still running or once it has been evicted
+#[tauri::command]
+fn get_trace_outcome(trace_id: String, state: tauri::State<'_, AppState>) -> Option<TraceOutcome> {
+    find_trace_outcome(&state.trace_outcomes, &trace_id)
+}
+
+// Counts a spawned trace tool as active until the guard is dropped, which
+// execute_trace_with_cancel only does after the child has been waited on
+struct ActiveChildGuard(Arc<AtomicUsize>);
+
+impl ActiveChildGuard {
+    fn new(counter: Arc<AtomicUsize>) -> Self {
+        counter.fetch_add(1, Ordering::SeqCst);
+        ActiveChildGuard(counter)
+    }
+}
+
+impl Drop for ActiveChildGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[tauri::command]
+fn get_active_child_count(state: tauri::State<'_, AppState>) -> usize {
+    state.active_children.load(Ordering::SeqCst)
 }
 
 #[tauri::command]
 async fn run_trace(
     app: tauri::AppHandle,
     target: String,
-    options: TraceOptions,
+    mut options: TraceOptions,
     state: tauri::State<'_, AppState>,
 ) -> Result<String, String> {
     let pid = std::process::id();
@@ -321,31 +1039,53 @@ async fn run_trace(
         return Err(error_msg);
     }
 
+    if options.family == Some(IpFamily::Auto) {
+        options.family = Some(resolve_auto_family(&target).await);
+        tracing::debug!("[Rust] [TRACE] Auto family resolved to {:?} for target: {}", options.family, target);
+    }
+
     // Prepare command based on OS
     let (cmd, args) = prepare_trace_command(&target, &options)?;
     tracing::debug!("[Rust] [TRACE] Prepared command: '{}' with args: {:?}", cmd, args);
 
+    if options.source_port.is_some() && is_busybox_tool(&cmd).await {
+        return Err("BusyBox traceroute cannot set a source port".to_string());
+    }
+
     // Create a unique ID for this trace
     let trace_id = uuid::Uuid::new_v4().to_string();
     tracing::debug!("[Rust] [TRACE] Generated trace_id: {}", trace_id);
     let trace_id_for_cleanup = trace_id.clone(); // Clone for the cleanup task
     
     let cancel_notify = Arc::new(Notify::new());
-    let cancel_for_task = cancel_notify.clone();
     let cancel_for_exec = cancel_notify.clone();
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let cancelled_for_task = cancelled.clone();
+    let outcomes_for_task = state.inner().trace_outcomes.clone();
     let app_for_task = app.clone();
     let trace_id_for_task = trace_id.clone();
     let state_for_cleanup = state.inner().running_traces.clone(); // Clone the Arc<Mutex<>> for cleanup
+    let active_children = state.inner().active_children.clone();
     
     // Execute the traceroute command in a cancellable task
-    let trace_future = execute_trace_with_cancel(app_for_task, cmd, args, cancel_for_exec, trace_id_for_task.clone());
+    let trace_id_for_exec = trace_id_for_task.clone();
+    let trace_future = async move {
+        // Let the UI lay out every hop as pending before any line arrives, once per trace.
+        // Each one is then replaced by its hop:update.
+        let max_hops = options.max_hops.unwrap_or(DEFAULT_MAX_HOPS);
+        emit_hops_init(&app_for_task, &trace_id_for_exec, max_hops);
+        if options.continuous.unwrap_or(false) {
+            run_continuous_trace(app_for_task, cmd, args, options, cancel_for_exec, trace_id_for_exec, active_children).await
+        } else {
+            let result = execute_trace_with_cancel(Some(app_for_task.clone()), cmd, args, options, cancel_for_exec, trace_id_for_exec.clone(), active_children).await?;
+            finish_trace(&app_for_task, &trace_id_for_exec, max_hops, &result).await;
+            Ok(result)
+        }
+    };
     tracing::debug!("[Rust] [TRACE] About to spawn async task");
     let handle = tokio::spawn(async move {
         tracing::debug!("[Rust] [TRACE] Inside spawned task for trace_id: {}", trace_id_for_task);
-        let result = tokio::select! {
-            result = trace_future => result,
-            _ = cancel_for_task.notified() => Err("Trace cancelled by user".to_string()),
-        };
+        let result = run_and_record_outcome(trace_future, &outcomes_for_task, &trace_id_for_cleanup, &cancelled_for_task).await;
         
         tracing::debug!("[Rust] [TRACE] Spawned task completed for trace_id: {}, result success: {}", trace_id_for_cleanup, result.is_ok());
         // Clean up the completed trace from the map after completion
@@ -363,7 +1103,7 @@ async fn run_trace(
         let mut running_traces: std::sync::MutexGuard<'_, HashMap<String, RunningTrace>> = state.running_traces.lock().expect("Failed to acquire mutex lock for storing trace");
         running_traces.insert(
             trace_id.clone(), 
-            RunningTrace { cancel_notify, handle }
+            RunningTrace { cancel_notify, cancelled, handle }
         );
         tracing::debug!("[Rust] [TRACE] Stored running trace with ID: {}", trace_id);
     }
@@ -376,50 +1116,399 @@ async fn run_trace(
     result
 }
 
-async fn execute_trace_with_cancel(
-    app: tauri::AppHandle,
-    cmd: String, 
-    args: Vec<String>, 
-    cancel_notify: Arc<Notify>,
-    trace_id: String
-) -> Result<TraceResult, String> {
-    let pid = std::process::id();
-    tracing::info!("[Rust] [TRACE] execute_trace_with_cancel start cmd='{}' args='{:?}' pid={}", cmd, args, pid);
-    
-    // Create the command
-    let mut cmd_builder = Command::new(&cmd);
-    cmd_builder
-        .args(&args)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped());
+// Hop limit when maxHops isn't given. Always passed to the tool, macOS traceroute would
+// otherwise go up to net.inet.ip.ttl (64) past the placeholders from trace:hops_init.
+const DEFAULT_MAX_HOPS: u32 = 30;
 
-    #[cfg(windows)]
-    {
-        // CREATE_NO_WINDOW
-        cmd_builder.creation_flags(0x08000000);
-    }
+// How often a running trace sends trace:heartbeat, whether or not the tool printed anything
+const TRACE_HEARTBEAT_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_secs(3);
 
-    let mut child = cmd_builder
-        .spawn()
-        .map_err(|e| {
-            let error_msg = format!("Failed to start {}: {}", cmd, e);
-            tracing::error!("[Rust] [TRACE] Failed to spawn command: {}", error_msg);
-            error_msg
-        })?;
-    
-    let child_pid = child.id().unwrap_or(0);
-    tracing::info!("[Rust] [TRACE] Child process spawned successfully pid={} cmd='{}'", child_pid, cmd);
+// Paces trace:heartbeat for one trace, across all runs of a continuous one and the pauses
+// between them. The first beat comes one period in, a beat held up by a busy loop pushes the
+// next ones back instead of firing in a burst.
+struct TraceHeartbeat {
+    started: std::time::Instant,
+    interval: tokio::time::Interval,
+    beats: u64,
+}
 
-    // Create readers for both stdout and stderr
-    let stdout = child.stdout.take().ok_or_else(|| "Failed to get stdout".to_string())?;
-    let stderr = child.stderr.take().ok_or_else(|| "Failed to get stderr".to_string())?;
+impl TraceHeartbeat {
+    fn new(period: tokio::time::Duration) -> Self {
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        Self {
+            started: std::time::Instant::now(),
+            interval,
+            beats: 0,
+        }
+    }
 
-    let mut out_reader = BufReader::new(stdout).lines();
-    let mut err_reader = BufReader::new(stderr).lines();
-    
-    let mut raw_output = String::new();
-    let mut hops = Vec::new();
+    // Waits for the next beat and returns the time since the trace started
+    async fn tick(&mut self) -> std::time::Duration {
+        self.interval.tick().await;
+        self.beats += 1;
+        self.started.elapsed()
+    }
+}
+
+// Line reader for the trace tool's output. Reads into one reusable buffer and hands out lines
+// borrowed from it, so unlike BufReader::lines() reading a line allocates nothing. Invalid UTF-8
+// (e.g. tracert output in a non-UTF-8 console code page) is replaced instead of failing the read.
+struct ChunkedLines<R> {
+    reader: R,
+    buf: Vec<u8>,
+    // Start of the bytes not handed out yet
+    pos: usize,
+    eof: bool,
+}
+
+impl<R: tokio::io::AsyncRead + Unpin> ChunkedLines<R> {
+    const CHUNK_SIZE: usize = 8192;
+
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buf: Vec::with_capacity(Self::CHUNK_SIZE),
+            pos: 0,
+            eof: false,
+        }
+    }
+
+    // Cancel safe like Lines::next_line: read_buf either appends to `buf` or reads nothing.
+    // The returned line borrows the buffer until the next call.
+    async fn next_line(&mut self) -> std::io::Result<Option<std::borrow::Cow<'_, str>>> {
+        loop {
+            if let Some(offset) = memchr::memchr(b'\n', &self.buf[self.pos..]) {
+                let start = self.pos;
+                self.pos += offset + 1;
+                return Ok(Some(Self::decode(&self.buf[start..start + offset])));
+            }
+
+            if self.eof {
+                // Last line without a trailing newline
+                if self.pos < self.buf.len() {
+                    let start = self.pos;
+                    self.pos = self.buf.len();
+                    return Ok(Some(Self::decode(&self.buf[start..])));
+                }
+                return Ok(None);
+            }
+
+            // Drop lines already handed out before reading the next chunk
+            if self.pos > 0 {
+                self.buf.drain(..self.pos);
+                self.pos = 0;
+            }
+            self.buf.reserve(Self::CHUNK_SIZE);
+
+            if tokio::io::AsyncReadExt::read_buf(&mut self.reader, &mut self.buf).await? == 0 {
+                self.eof = true;
+            }
+        }
+    }
+
+    fn decode(line: &[u8]) -> std::borrow::Cow<'_, str> {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        String::from_utf8_lossy(line)
+    }
+}
+
+// Probes per hop when probesPerHop isn't given, tracert always sends 3
+const DEFAULT_PROBES_PER_HOP: u32 = 3;
+// Per-probe wait of traceroute when timeoutMs isn't given
+const DEFAULT_PROBE_TIMEOUT_MS: u64 = 5000;
+// Slack on top of a silent hop's probe time before the trace tool is considered stuck
+const TRACE_IDLE_SLACK: tokio::time::Duration = tokio::time::Duration::from_secs(5);
+// Extra allowance when the tool resolves hop names itself, its PTR lookups can stall a hop too
+const TRACE_IDLE_DNS_SLACK: tokio::time::Duration = tokio::time::Duration::from_secs(20);
+// Upper bound of the waits derived from timeoutMs, deadlines are set as Instant::now() + wait
+// and an absurd timeoutMs from the frontend must not overflow them
+const MAX_TRACE_WAIT: tokio::time::Duration = tokio::time::Duration::from_secs(24 * 60 * 60);
+
+// Probes the tool sends per hop, tracert always sends 3
+fn expected_probes(options: &TraceOptions) -> u32 {
+    if cfg!(windows) {
+        DEFAULT_PROBES_PER_HOP
+    } else {
+        options.probes_per_hop.unwrap_or(DEFAULT_PROBES_PER_HOP)
+    }
+}
+
+// Longest a healthy trace can go without printing a line. A black-hole hop only prints once
+// every one of its probes has timed out, so this grows with timeoutMs and probesPerHop.
+fn trace_idle_timeout(options: &TraceOptions) -> tokio::time::Duration {
+    let probe_time = tokio::time::Duration::from_millis(
+        options.timeout_ms.unwrap_or(DEFAULT_PROBE_TIMEOUT_MS).saturating_mul(expected_probes(options) as u64),
+    );
+    let dns_slack = if options.resolve_dns == Some(false) {
+        tokio::time::Duration::ZERO
+    } else {
+        TRACE_IDLE_DNS_SLACK
+    };
+    (probe_time + TRACE_IDLE_SLACK + dns_slack).min(MAX_TRACE_WAIT)
+}
+
+// How long a hop still missing probes waits before it is settled as is, every one of them
+// could have timed out by then
+fn hop_settle_timeout(options: &TraceOptions) -> tokio::time::Duration {
+    let millis = options.timeout_ms.unwrap_or(DEFAULT_PROBE_TIMEOUT_MS)
+        .saturating_mul(expected_probes(options) as u64)
+        .saturating_add(1000);
+    tokio::time::Duration::from_millis(millis).min(MAX_TRACE_WAIT)
+}
+
+// Follows the hop currently being printed so one hop:settled event can be sent once all of its
+// probes are in. Unix traceroute prints probes answered by a different router on a continuation
+// line without a hop number, e.g. "    10.0.0.2 (10.0.0.2)  6.1 ms  6.3 ms".
+struct HopSettler {
+    expected_probes: usize,
+    latency_precision: u32,
+    pending: Option<HopData>,
+}
+
+impl HopSettler {
+    fn new(expected_probes: usize, latency_precision: u32) -> Self {
+        HopSettler { expected_probes, latency_precision, pending: None }
+    }
+
+    // A new hop line. Returns the hops settled by it: the previous hop if it never completed,
+    // then this one if it already carries every probe.
+    fn start_hop(&mut self, hop: &HopData) -> Vec<HopData> {
+        let mut settled: Vec<HopData> = self.pending.take().into_iter().collect();
+        if hop.latencies.len() >= self.expected_probes {
+            settled.push(hop.clone());
+        } else {
+            self.pending = Some(hop.clone());
+        }
+        settled
+    }
+
+    // A continuation line of the pending hop. Returns the merged hop and whether it is now settled.
+    fn continue_hop(&mut self, line: &str) -> Option<(HopData, bool)> {
+        if !line.starts_with(char::is_whitespace) {
+            return None;
+        }
+        let pending = self.pending.as_mut()?;
+        let extra = parse_traceroute_line(&format!("{} {}", pending.hop, line.trim()))?;
+
+        pending.latencies.extend(extra.latencies);
+        pending.probe_ips.extend(extra.probe_ips);
+        if pending.ip.is_none() {
+            pending.ip = extra.ip;
+            pending.host = extra.host;
+        }
+        let valid: Vec<f64> = pending.latencies.iter().flatten().copied().collect();
+        if !valid.is_empty() {
+            let avg = valid.iter().sum::<f64>() / valid.len() as f64;
+            pending.avg_latency = Some(normalize_latency(avg, self.latency_precision));
+            if pending.status == "timeout" {
+                pending.status = "success".to_string();
+            }
+        }
+
+        let merged = pending.clone();
+        if merged.latencies.len() >= self.expected_probes {
+            self.pending = None;
+            Some((merged, true))
+        } else {
+            Some((merged, false))
+        }
+    }
+
+    // Settle whatever is still pending, on the per-hop timeout or at the end of the trace
+    fn flush(&mut self) -> Option<HopData> {
+        self.pending.take()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+// Lines printed by the trace tool, each tagged with the stream it came from. A line's index is
+// its sequence number in read order, so each stream can be recovered on its own and the
+// combined view is rebuilt in that order instead of from whatever the loop appended last.
+#[derive(Default)]
+struct RawTranscript {
+    lines: Vec<(OutputStream, String)>,
+}
+
+impl RawTranscript {
+    fn push(&mut self, stream: OutputStream, line: &str) {
+        self.lines.push((stream, escape_control_chars(line).into_owned()));
+    }
+
+    fn stream(&self, stream: OutputStream) -> String {
+        self.render(|s| s == stream)
+    }
+
+    fn combined(&self) -> String {
+        self.render(|_| true)
+    }
+
+    fn render(&self, include: impl Fn(OutputStream) -> bool) -> String {
+        let mut out = String::new();
+        for (stream, line) in &self.lines {
+            if include(*stream) {
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+        out
+    }
+}
+
+// Hops of one trace enriched at the same time when enrichConcurrency isn't given
+const DEFAULT_ENRICH_CONCURRENCY: usize = 6;
+// More would only queue on OUTBOUND_REQUESTS, and Semaphore::new panics past MAX_PERMITS
+const MAX_ENRICH_CONCURRENCY: usize = 64;
+
+fn enrich_concurrency(options: &TraceOptions) -> usize {
+    options.enrich_concurrency.unwrap_or(DEFAULT_ENRICH_CONCURRENCY).clamp(1, MAX_ENRICH_CONCURRENCY)
+}
+
+// An output line waiting its turn in the enrichment pipeline. Continuation lines go through the
+// pipeline too so they reach the settler after the hop they belong to.
+enum PipelineLine {
+    Hop(Box<HopData>),
+    Continuation(String),
+}
+
+// Runs `task` once a permit of the per-trace enrichment limit is free. Online geo lookups take a
+// global OUTBOUND_REQUESTS permit while holding this one, always in that order, so a trace never
+// holds a global permit while waiting for one of its own.
+async fn run_limited<F: std::future::Future>(limit: Arc<tokio::sync::Semaphore>, task: F) -> F::Output {
+    let _permit = limit.acquire_owned().await;
+    task.await
+}
+
+fn enrich_in_pipeline(
+    mut hop_data: HopData,
+    limit: &Arc<tokio::sync::Semaphore>,
+    resolve_dns: bool,
+    geo_fallback_url: Option<Arc<str>>,
+) -> futures::future::BoxFuture<'static, PipelineLine> {
+    use futures::FutureExt;
+    run_limited(limit.clone(), async move {
+        enrich_hop(&mut hop_data, resolve_dns, geo_fallback_url.as_deref()).await;
+        PipelineLine::Hop(Box::new(hop_data))
+    })
+    .boxed()
+}
+
+// One trace as the UI sees it. A continuous trace runs the tool many times and keeps this
+// across the runs, so events carry on from where the last run left them.
+struct TraceSession {
+    trace_id: String,
+    // Last aggregate status sent per hop number, for hop:status transitions
+    hop_statuses: HashMap<u32, String>,
+    // Lets the UI tell a slow trace from a hung one and keep its elapsed timer accurate
+    heartbeat: TraceHeartbeat,
+}
+
+impl TraceSession {
+    fn new(trace_id: String) -> Self {
+        Self {
+            trace_id,
+            hop_statuses: HashMap::new(),
+            heartbeat: TraceHeartbeat::new(TRACE_HEARTBEAT_INTERVAL),
+        }
+    }
+
+    fn emit_heartbeat(&self, app: Option<&AppHandle>, elapsed: std::time::Duration) {
+        // Hops that reported a status so far, over every run of the trace
+        emit_trace_heartbeat(app, &self.trace_id, elapsed, self.hop_statuses.len());
+    }
+}
+
+// `app` is None when running headless, hops are then printed to stdout as JSON lines
+async fn execute_trace_with_cancel(
+    app: Option<tauri::AppHandle>,
+    cmd: String, 
+    args: Vec<String>, 
+    options: TraceOptions,
+    cancel_notify: Arc<Notify>,
+    trace_id: String,
+    active_children: Arc<AtomicUsize>,
+) -> Result<TraceResult, String> {
+    let mut session = TraceSession::new(trace_id);
+    execute_trace_run(app, cmd, args, options, cancel_notify, active_children, &mut session).await
+}
+
+// One run of the trace tool as part of `session`
+async fn execute_trace_run(
+    app: Option<tauri::AppHandle>,
+    cmd: String,
+    args: Vec<String>,
+    options: TraceOptions,
+    cancel_notify: Arc<Notify>,
+    active_children: Arc<AtomicUsize>,
+    session: &mut TraceSession,
+) -> Result<TraceResult, String> {
+    let trace_id = session.trace_id.clone();
+    let pid = std::process::id();
+    tracing::info!("[Rust] [TRACE] execute_trace_with_cancel start cmd='{}' args='{:?}' pid={}", cmd, args, pid);
+    
+    // Create the command
+    let mut cmd_builder = Command::new(&cmd);
+    cmd_builder
+        .args(&args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // Backstop for early returns, the normal paths kill and wait explicitly
+        .kill_on_drop(true);
+
+    #[cfg(windows)]
+    {
+        // CREATE_NO_WINDOW
+        cmd_builder.creation_flags(0x08000000);
+    }
+
+    let mut child = cmd_builder
+        .spawn()
+        .map_err(|e| {
+            let error_msg = format!("Failed to start {}: {}", cmd, e);
+            tracing::error!("[Rust] [TRACE] Failed to spawn command: {}", error_msg);
+            error_msg
+        })?;
+    
+    let child_pid = child.id().unwrap_or(0);
+    let _active_child = ActiveChildGuard::new(active_children);
+    tracing::info!("[Rust] [TRACE] Child process spawned successfully pid={} cmd='{}'", child_pid, cmd);
+
+    // Create readers for both stdout and stderr
+    let stdout = child.stdout.take().ok_or_else(|| "Failed to get stdout".to_string())?;
+    let stderr = child.stderr.take().ok_or_else(|| "Failed to get stderr".to_string())?;
+
+    let (mut out_reader, mut err_reader) = (ChunkedLines::new(stdout), ChunkedLines::new(stderr));
+    
+    let mut transcript = RawTranscript::default();
+    let mut hops: Vec<HopData> = Vec::new();
+    let mut resolved_ip: Option<String> = None;
     let start_time = chrono::Utc::now().to_rfc3339();
+    let resolve_dns = options.resolve_dns.unwrap_or(false);
+    let emit_lines = should_emit_line(&options);
+    // Settings are read once per trace, not for every hop looked up online
+    let geo_fallback_url: Option<Arc<str>> = options.geo_fallback_online
+        .unwrap_or(false)
+        .then(|| geo_fallback_url().into());
+    let latency_precision = options.latency_precision.unwrap_or(DEFAULT_LATENCY_PRECISION);
+    
+    // Hops are enriched concurrently, up to the per-trace limit, and handed on in output order
+    let enrich_limit = Arc::new(tokio::sync::Semaphore::new(enrich_concurrency(&options)));
+    let mut pipeline: futures::stream::FuturesOrdered<futures::future::BoxFuture<'static, PipelineLine>> =
+        futures::stream::FuturesOrdered::new();
+    
+    // Continuation lines are always merged into their hop, the opt-in part is the hop:settled
+    // event sent once per hop after its last probe
+    let expected_probes = expected_probes(&options);
+    let mut settler = HopSettler::new(expected_probes as usize, latency_precision);
+    let settled_events = options.settled_events.unwrap_or(false);
+    let settle_timeout = hop_settle_timeout(&options);
+    let settle_deadline = tokio::time::sleep(settle_timeout);
+    tokio::pin!(settle_deadline);
     
     // Counters for diagnostic purposes
     let mut stdout_lines_read = 0;
@@ -432,64 +1521,47 @@ async fn execute_trace_with_cancel(
     
     tracing::info!("[Rust] [TRACE] Starting to read stdout and stderr streams");
     
-    while !stdout_closed || !stderr_closed {
+    // A tool that keeps its streams open without printing anything (e.g. stuck on a
+    // black-hole hop) would otherwise keep this loop alive forever
+    let idle_timeout = trace_idle_timeout(&options);
+    let idle_deadline = tokio::time::sleep(idle_timeout);
+    tokio::pin!(idle_deadline);
+    
+    // Keep going after both streams close until the last hops are through enrichment
+    while !stdout_closed || !stderr_closed || !pipeline.is_empty() {
         tokio::select! {
-            line = out_reader.next_line() => {
+            line = out_reader.next_line(), if !stdout_closed => {
                 match line {
                     Ok(Some(line)) => {
+                        idle_deadline.as_mut().reset(tokio::time::Instant::now() + idle_timeout);
                         stdout_lines_read += 1;
                         if stdout_lines_read <= max_diag_lines {
                             tracing::info!("[Rust] [TRACE] stdout line {}: {}", stdout_lines_read, line);
                         }
                         // Emit event for UI update
-                        emit_trace_line(&app, &trace_id, stdout_lines_read, &line);
+                        if emit_lines {
+                            if let Some(ref app) = app {
+                                emit_trace_line(app, &trace_id, stdout_lines_read, &line);
+                            }
+                        }
                         
-                        raw_output.push_str(&line);
-                        raw_output.push('\n');
+                        transcript.push(OutputStream::Stdout, &line);
+                        
+                        if resolved_ip.is_none() {
+                            resolved_ip = parse_resolved_ip(&line);
+                        }
                         
                         // Try to parse the line for hop data
                         if let Some(mut hop_data) = parse_traceroute_line(&line) {
+                            hop_data.received_at = chrono::Utc::now().to_rfc3339();
+                            hop_data.avg_latency = hop_data.avg_latency.map(|ms| normalize_latency(ms, latency_precision));
                             tracing::debug!("[Rust] [TRACE] Parsed hop data: hop={}, ip={:?}, latencies={:?}", 
                                           hop_data.hop, hop_data.ip, hop_data.latencies);
                             
-                            // Enrich this single hop with geolocation data immediately
-                            if let Some(ref ip) = hop_data.ip {
-                                if let Ok(geo_result) = geo_lookup_inner(ip.to_string()).await {
-                                    // Only set geo when lat/lng exist, don't set fake (0,0) coordinates
-                                    if let (Some(lat), Some(lng)) = (geo_result.lat, geo_result.lng) {
-                                        // Convert GeoResult to GeoLocation
-                                        hop_data.geo = Some(GeoLocation {
-                                            lat: Some(lat),
-                                            lng: Some(lng),
-                                            city: geo_result.city,
-                                            country: geo_result.country,
-                                            country_code: geo_result.country_code,
-                                        });
-                                    } else {
-                                        // Keep location text for private/internal but don't set geo coords
-                                        hop_data.geo = if geo_result.city.as_deref() == Some("Private/Internal") {
-                                            Some(GeoLocation {
-                                                lat: None,
-                                                lng: None,
-                                                city: geo_result.city,
-                                                country: geo_result.country,
-                                                country_code: geo_result.country_code,
-                                            })
-                                        } else {
-                                            None
-                                        };
-                                    }
-                                }
-                            }
-                            
-                            hops.push(hop_data.clone()); // Store the enriched hop
-                            
-                            // Emit the enriched hop immediately - now with complete data
-                            if let Err(e) = emit_hop_update(app.clone(), &trace_id, hop_data).await {
-                                tracing::warn!("[Rust] [TRACE] Failed to emit hop update: {}", e);
-                            }
+                            // Enrich this hop with DNS and geolocation data, it is emitted once that's done
+                            pipeline.push_back(enrich_in_pipeline(hop_data, &enrich_limit, resolve_dns, geo_fallback_url.clone()));
                         } else {
-                            tracing::debug!("[Rust] [TRACE] Line did not parse as hop: {}", line);
+                            pipeline.push_back(Box::pin(futures::future::ready(PipelineLine::Continuation(line.into_owned()))));
                         }
                     }
                     Ok(None) => {
@@ -499,61 +1571,33 @@ async fn execute_trace_with_cancel(
                     Err(e) => {
                         let error_msg = format!("stdout read error: {}", e);
                         tracing::error!("[Rust] [TRACE] {}", error_msg);
+                        let _ = child.kill().await;
                         return Err(error_msg);
                     }
                 }
             }
-            line = err_reader.next_line() => {
+            line = err_reader.next_line(), if !stderr_closed => {
                 match line {
                     Ok(Some(line)) => {
+                        idle_deadline.as_mut().reset(tokio::time::Instant::now() + idle_timeout);
                         stderr_lines_read += 1;
                         if stderr_lines_read <= max_diag_lines {
                             tracing::debug!("[Rust] [TRACE] stderr line {}: {}", stderr_lines_read, line);
                         }
-                        raw_output.push_str(&line);
-                        raw_output.push('\n');
+                        transcript.push(OutputStream::Stderr, &line);
+                        
+                        if resolved_ip.is_none() {
+                            resolved_ip = parse_resolved_ip(&line);
+                        }
                         
                         // On Windows, tracert writes to stderr, so we should also try to parse stderr lines
                         if let Some(mut hop_data) = parse_traceroute_line(&line) {
+                            hop_data.received_at = chrono::Utc::now().to_rfc3339();
+                            hop_data.avg_latency = hop_data.avg_latency.map(|ms| normalize_latency(ms, latency_precision));
                             tracing::debug!("[Rust] [TRACE] Parsed hop data from stderr: hop={}, ip={:?}, latencies={:?}", 
                                           hop_data.hop, hop_data.ip, hop_data.latencies);
                             
-                            // Enrich this single hop with geolocation data immediately
-                            if let Some(ref ip) = hop_data.ip {
-                                if let Ok(geo_result) = geo_lookup_inner(ip.to_string()).await {
-                                    // Only set geo when lat/lng exist, don't set fake (0,0) coordinates
-                                    if let (Some(lat), Some(lng)) = (geo_result.lat, geo_result.lng) {
-                                        // Convert GeoResult to GeoLocation
-                                        hop_data.geo = Some(GeoLocation {
-                                            lat: Some(lat),
-                                            lng: Some(lng),
-                                            city: geo_result.city,
-                                            country: geo_result.country,
-                                            country_code: geo_result.country_code,
-                                        });
-                                    } else {
-                                        // Keep location text for private/internal but don't set geo coords
-                                        hop_data.geo = if geo_result.city.as_deref() == Some("Private/Internal") {
-                                            Some(GeoLocation {
-                                                lat: None,
-                                                lng: None,
-                                                city: geo_result.city,
-                                                country: geo_result.country,
-                                                country_code: geo_result.country_code,
-                                            })
-                                        } else {
-                                            None
-                                        };
-                                    }
-                                }
-                            }
-                            
-                            hops.push(hop_data.clone()); // Store the enriched hop
-                            
-                            // Emit the enriched hop immediately
-                            if let Err(e) = emit_hop_update(app.clone(), &trace_id, hop_data).await {
-                                tracing::warn!("[Rust] [TRACE] Failed to emit hop update: {}", e);
-                            }
+                            pipeline.push_back(enrich_in_pipeline(hop_data, &enrich_limit, resolve_dns, geo_fallback_url.clone()));
                         } else {
                             tracing::debug!("[Rust] [TRACE] stderr line did not parse as hop: {}", line);
                         }
@@ -565,38 +1609,109 @@ async fn execute_trace_with_cancel(
                     Err(e) => {
                         let error_msg = format!("stderr read error: {}", e);
                         tracing::error!("[Rust] [TRACE] {}", error_msg);
+                        let _ = child.kill().await;
                         return Err(error_msg);
                     }
                 }
             }
+            Some(line) = futures::StreamExt::next(&mut pipeline), if !pipeline.is_empty() => {
+                match line {
+                    PipelineLine::Hop(hop_data) => {
+                        let hop_data = *hop_data;
+                        hops.push(hop_data.clone()); // Store the enriched hop
+                        
+                        let settled = settler.start_hop(&hop_data);
+                        settle_deadline.as_mut().reset(tokio::time::Instant::now() + settle_timeout);
+                        
+                        // Emit the enriched hop immediately - now with complete data
+                        emit_hop_status(app.as_ref(), &trace_id, &mut session.hop_statuses, &hop_data);
+                        if let Err(e) = emit_hop(app.as_ref(), &trace_id, hop_data.clone()).await {
+                            tracing::warn!("[Rust] [TRACE] Failed to emit hop update: {}", e);
+                        }
+                        emit_hop_geo(app.as_ref(), &trace_id, &hop_data);
+                        if settled_events {
+                            for hop in settled {
+                                emit_hop_settled(app.as_ref(), &trace_id, hop);
+                            }
+                        }
+                    }
+                    PipelineLine::Continuation(line) => {
+                        if let Some((merged, settled)) = settler.continue_hop(&line) {
+                            tracing::debug!("[Rust] [TRACE] Continuation line for hop {}: {}", merged.hop, line);
+                            if let Some(last) = hops.last_mut().filter(|last| last.hop == merged.hop) {
+                                *last = merged.clone();
+                            }
+                            emit_hop_status(app.as_ref(), &trace_id, &mut session.hop_statuses, &merged);
+                            if let Err(e) = emit_hop(app.as_ref(), &trace_id, merged.clone()).await {
+                                tracing::warn!("[Rust] [TRACE] Failed to emit hop update: {}", e);
+                            }
+                            if settled && settled_events {
+                                emit_hop_settled(app.as_ref(), &trace_id, merged);
+                            }
+                        } else {
+                            tracing::debug!("[Rust] [TRACE] Line did not parse as hop: {}", line);
+                        }
+                    }
+                }
+            }
             _ = cancel_notify.notified() => {
                 tracing::info!("[Rust] [TRACE] Cancel notification received, killing process pid={}", child_pid);
                 let _ = child.kill().await;
+                let raw_output = transcript.combined();
                 tracing::debug!("[Rust] raw_output bytes: {}", raw_output.len());
                 tracing::debug!("[Rust] raw_output preview: {}", raw_output.lines().take(5).collect::<Vec<_>>().join(" | "));
                 return Err("[Rust] Trace cancelled by user".to_string());
             }
+            _ = &mut settle_deadline, if settler.pending.is_some() => {
+                if let Some(hop) = settler.flush() {
+                    tracing::debug!("[Rust] [TRACE] Hop {} settled by timeout", hop.hop);
+                    if settled_events {
+                        emit_hop_settled(app.as_ref(), &trace_id, hop);
+                    }
+                }
+            }
+            elapsed = session.heartbeat.tick() => {
+                // Deliberately leaves idle_deadline alone, a heartbeat is not output
+                session.emit_heartbeat(app.as_ref(), elapsed);
+            }
+            _ = &mut idle_deadline, if !stdout_closed || !stderr_closed => {
+                let error_msg = format!("Trace produced no output for {} seconds, killing process pid={}", idle_timeout.as_secs(), child_pid);
+                tracing::error!("[Rust] [TRACE] {}", error_msg);
+                let _ = child.kill().await;
+                return Err(error_msg);
+            }
         }
     }
     
     tracing::info!("[Rust] [TRACE] Both stdout and stderr closed, about to wait for child process pid={}", child_pid);
+    
+    if let Some(hop) = settler.flush().filter(|_| settled_events) {
+        emit_hop_settled(app.as_ref(), &trace_id, hop);
+    }
+    let raw_output = transcript.combined();
     tracing::info!("[Rust] [TRACE] Hops collected so far: {}, Raw output length: {}", hops.len(), raw_output.len());
     
+    // Both pipes are at EOF, release them before reaping the child
+    drop(out_reader);
+    drop(err_reader);
+    
     // Wait for the process to finish with a timeout to prevent hanging
-    let exit_status = tokio::time::timeout(
+    let exit_status = match tokio::time::timeout(
         tokio::time::Duration::from_secs(60), // 60 second timeout
         child.wait()
-    ).await
-        .map_err(|e| {
-            let error_msg = format!("Process timed out after 60 seconds, killing process pid={}: {}", child_pid, e);
-            tracing::error!("[Rust] [TRACE] {}", error_msg);
-            error_msg
-        })?
-        .map_err(|e| {
+    ).await {
+        Ok(status) => status.map_err(|e| {
             let error_msg = format!("Failed to wait for process: {}", e);
             tracing::error!("[Rust] [TRACE] {}", error_msg);
             error_msg
-        })?;
+        })?,
+        Err(e) => {
+            let error_msg = format!("Process timed out after 60 seconds, killing process pid={}: {}", child_pid, e);
+            tracing::error!("[Rust] [TRACE] {}", error_msg);
+            let _ = child.kill().await;
+            return Err(error_msg);
+        }
+    };
     
     tracing::info!("[Rust] [TRACE] Child process finished with exit code: {}", exit_status.code().unwrap_or(-1));
     
@@ -610,669 +1725,4515 @@ async fn execute_trace_with_cancel(
     
     tracing::info!("[Rust] [TRACE] Trace completed - raw_output len: {}, hops count: {}", raw_output.len(), hops.len());
 
+    if let Some(sample_lines) = parse_failed_sample(&hops, &raw_output) {
+        tracing::warn!("[Rust] [TRACE] No hops parsed from {} bytes of output, first lines: {:?}", raw_output.len(), sample_lines);
+        if let Some(ref app) = app {
+            emit_trace_warning(app, &trace_id, "parse_failed", "Trace output could not be parsed into hops", sample_lines);
+        }
+    }
+
+    let (error, reached_destination) = path_outcome(&hops, resolved_ip.as_deref());
+    if let Some(ref e) = error {
+        tracing::warn!("[Rust] [TRACE] Trace failed: {}", e);
+    }
+
     let result = TraceResult {
         target: args.last().unwrap_or(&"unknown".to_string()).clone(),
-        resolved_ip: None,
+        resolved_ip,
         hops,
         raw_output,
+        stdout_raw: transcript.stream(OutputStream::Stdout),
+        stderr_raw: transcript.stream(OutputStream::Stderr),
         start_time,
         end_time,
+        reached_destination,
+        error,
     };
     
-    tracing::info!("[Rust] [TRACE] About to emit completion event for trace_id: {}", trace_id);
-    // Emit completion event to notify frontend
-    emit_trace_complete(&app, &trace_id, &result);
-    tracing::info!("[Rust] [TRACE] Completion event emitted for trace_id: {}", trace_id);
-    
+    // trace:complete is left to the caller, a continuous trace sends it once when it stops
     Ok(result)
 }
 
-#[tauri::command]
-async fn stop_trace(trace_id: String, state: tauri::State<'_, AppState>) -> Result<(), String> {
-    let mut running_traces: std::sync::MutexGuard<'_, HashMap<String, RunningTrace>> = state.running_traces.lock().expect("Failed to acquire mutex lock for stopping trace");
-    if let Some(running_trace) = running_traces.remove(&trace_id) {
-        running_trace.cancel_notify.notify_one();
-        
-        // Abort the task to ensure it stops immediately
-        running_trace.handle.abort();
-        
-        Ok(())
-    } else {
-        Err("Trace not found".to_string())
-    }
-}
-
+const DEFAULT_CONTINUOUS_INTERVAL_MS: u64 = 1000;
+// Number of recent RTT samples kept per hop for the continuous-mode statistics.
+// Older samples are discarded, the sent/received counters still cover the whole run.
+const DEFAULT_STATS_WINDOW: usize = 300;
+const MAX_STATS_WINDOW: usize = 100_000;
 
-fn is_valid_target(target: &str) -> bool {
-    // Basic validation to prevent command injection
-    // Allow alphanumeric, dots, hyphens, colons (for IPv6), and underscores
-    // Also allow basic domain names
-    
-    // Check for potentially dangerous characters
-    if target.contains(' ') || target.contains('&') || target.contains('|') || 
-       target.contains(';') || target.contains('`') || target.contains('$') ||
-       target.contains('(') || target.contains(')') || target.contains('<') ||
-       target.contains('>') || target.contains('"') || target.contains('\'') {
-        return false;
-    }
-    
-    // Check for valid IP or domain format
-    let is_ip = target.parse::<std::net::IpAddr>().is_ok();
-    let is_domain = {
-        // Basic domain validation: letters, digits, dots, hyphens
-        // Must contain at least one dot and not start/end with special chars
-        !target.is_empty() &&
-        target.len() <= 255 &&
-        target.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_') &&
-        target.starts_with(|c: char| c.is_ascii_alphanumeric()) &&
-        target.ends_with(|c: char| c.is_ascii_alphanumeric()) &&
-        target.chars().any(|c| c == '.') &&  // Must contain at least one dot
-        !target.contains("..")  // No double dots
-    };
-    
-    is_ip || is_domain
+// Rolling per-hop statistics across continuous runs
+#[derive(Default)]
+struct HopStats {
+    ip: Option<String>,
+    samples: std::collections::VecDeque<f64>,
+    sent: u64,
+    received: u64,
 }
 
-fn prepare_trace_command(target: &str, options: &TraceOptions) -> Result<(String, Vec<String>), String> {
-    let cmd;
-    let mut args = Vec::new();
+impl HopStats {
+    fn record(&mut self, hop: &HopData, window: usize) {
+        if hop.ip.is_some() {
+            self.ip = hop.ip.clone();
+        }
 
-    // Set command based on OS
-    #[cfg(windows)]
-    {
-        cmd = "tracert".to_string();
-        args.push("-d".to_string()); // Don't resolve addresses to names initially
-        
-        if let Some(max_hops) = options.max_hops {
-            args.push("-h".to_string());
-            args.push(max_hops.to_string());
+        for latency in &hop.latencies {
+            self.sent += 1;
+            if let Some(rtt) = latency {
+                self.received += 1;
+                self.samples.push_back(*rtt);
+                while self.samples.len() > window {
+                    self.samples.pop_front();
+                }
+            }
         }
-        
-        if let Some(timeout_ms) = options.timeout_ms {
-            args.push("-w".to_string());
-            args.push(timeout_ms.to_string());
+    }
+
+    fn loss_percent(&self) -> f64 {
+        if self.sent == 0 {
+            return 0.0;
         }
-        
-        if options.resolve_dns.unwrap_or(false) {
-            // Remove -d flag if DNS resolution is requested
-            if let Some(pos) = args.iter().position(|x| x == "-d") {
-                args.remove(pos);
-            }
+        (self.sent - self.received) as f64 * 100.0 / self.sent as f64
+    }
+
+    fn avg_ms(&self) -> Option<f64> {
+        if self.samples.is_empty() {
+            return None;
         }
+        Some(self.samples.iter().sum::<f64>() / self.samples.len() as f64)
     }
-    
-    #[cfg(unix)]
-    {
-        cmd = "traceroute".to_string();
-        
-        if let Some(max_hops) = options.max_hops {
-            args.push("-m".to_string());
-            args.push(max_hops.to_string());
+
+    // Mean absolute difference between consecutive RTT samples
+    fn jitter_ms(&self) -> Option<f64> {
+        if self.samples.len() < 2 {
+            return None;
         }
-        
-        if let Some(timeout_ms) = options.timeout_ms {
-            // Convert ms to seconds for traceroute
-            let timeout_sec = std::cmp::max((timeout_ms as f64 / 1000.0).ceil() as u64, 1);
-            args.push("-w".to_string());
-            args.push(timeout_sec.to_string());
+        let total: f64 = self.samples.iter()
+            .zip(self.samples.iter().skip(1))
+            .map(|(prev, next)| (next - prev).abs())
+            .sum();
+        Some(total / (self.samples.len() - 1) as f64)
+    }
+}
+
+#[derive(Serialize, Clone)]
+struct HopStatsSummary {
+    hop: u32,
+    ip: Option<String>,
+    sent: u64,
+    received: u64,
+    loss_percent: f64,
+    avg_ms: Option<f64>,
+    jitter_ms: Option<f64>,
+}
+
+#[derive(Serialize, Clone)]
+struct TraceStatsEvent {
+    trace_id: String,
+    iteration: u64,
+    hops: Vec<HopStatsSummary>,
+}
+
+fn emit_trace_stats(app: &AppHandle, trace_id: &str, iteration: u64, stats: &HashMap<u32, HopStats>) {
+    let mut hops: Vec<HopStatsSummary> = stats.iter()
+        .map(|(hop, hop_stats)| HopStatsSummary {
+            hop: *hop,
+            ip: hop_stats.ip.clone(),
+            sent: hop_stats.sent,
+            received: hop_stats.received,
+            loss_percent: hop_stats.loss_percent(),
+            avg_ms: hop_stats.avg_ms(),
+            jitter_ms: hop_stats.jitter_ms(),
+        })
+        .collect();
+    hops.sort_by_key(|summary| summary.hop);
+
+    let payload = TraceStatsEvent {
+        trace_id: trace_id.to_string(),
+        iteration,
+        hops,
+    };
+
+    if let Err(e) = app.emit("trace:stats", payload) {
+        tracing::warn!("[Rust] [TRACE] Failed to emit 'trace:stats' event: {}", e);
+    }
+}
+
+// Wait `interval` before the next continuous run, heartbeats keep going meanwhile.
+// false when the trace was cancelled during the wait.
+async fn pause_between_runs(
+    app: Option<&AppHandle>,
+    session: &mut TraceSession,
+    interval: tokio::time::Duration,
+    cancel_notify: &Notify,
+) -> bool {
+    let pause = tokio::time::sleep(interval);
+    tokio::pin!(pause);
+    loop {
+        tokio::select! {
+            _ = &mut pause => return true,
+            _ = cancel_notify.notified() => return false,
+            elapsed = session.heartbeat.tick() => session.emit_heartbeat(app, elapsed),
         }
-        
-        if let Some(probes) = options.probes_per_hop {
-            args.push("-q".to_string());
-            args.push(probes.to_string());
+    }
+}
+
+// Re-run the trace until cancelled, aggregating per-hop loss/latency/jitter across runs
+async fn run_continuous_trace(
+    app: tauri::AppHandle,
+    cmd: String,
+    args: Vec<String>,
+    options: TraceOptions,
+    cancel_notify: Arc<Notify>,
+    trace_id: String,
+    active_children: Arc<AtomicUsize>,
+) -> Result<TraceResult, String> {
+    let interval = tokio::time::Duration::from_millis(options.interval_ms.unwrap_or(DEFAULT_CONTINUOUS_INTERVAL_MS));
+    let stats_window = options.stats_window.unwrap_or(DEFAULT_STATS_WINDOW).clamp(1, MAX_STATS_WINDOW);
+    let max_hops = options.max_hops.unwrap_or(DEFAULT_MAX_HOPS);
+    let mut stats: HashMap<u32, HopStats> = HashMap::new();
+    let mut iteration: u64 = 0;
+    let mut last_result: Option<TraceResult> = None;
+    // hop:status carries on from the previous run, trace:hops_init was only sent once
+    let mut session = TraceSession::new(trace_id.clone());
+
+    let error = loop {
+        iteration += 1;
+        tracing::debug!("[Rust] [TRACE] Continuous trace {} iteration {}", trace_id, iteration);
+
+        let result = match execute_trace_run(
+            Some(app.clone()),
+            cmd.clone(),
+            args.clone(),
+            options.clone(),
+            cancel_notify.clone(),
+            active_children.clone(),
+            &mut session,
+        ).await {
+            Ok(result) => result,
+            Err(e) => break e,
+        };
+
+        for hop in &result.hops {
+            stats.entry(hop.hop).or_default().record(hop, stats_window);
         }
-        
-        if !options.resolve_dns.unwrap_or(true) {
-            args.push("-n".to_string()); // Skip reverse DNS lookup
+        emit_trace_stats(&app, &trace_id, iteration, &stats);
+        last_result = Some(result);
+
+        if !pause_between_runs(Some(&app), &mut session, interval, &cancel_notify).await {
+            break "Trace cancelled by user".to_string();
         }
+    };
+
+    tracing::info!("[Rust] [TRACE] Continuous trace {} stopped after {} iterations ({} heartbeats): {}",
+                   trace_id, iteration, session.heartbeat.beats, error);
+    // Only the last complete run is reported, once for the whole continuous trace
+    if let Some(ref result) = last_result {
+        finish_trace(&app, &trace_id, max_hops, result).await;
     }
+    Err(error)
+}
 
-    args.push(target.to_string());
+// How long a cancelled trace gets to kill and reap its child before the task is aborted
+const STOP_TRACE_GRACE: tokio::time::Duration = tokio::time::Duration::from_secs(5);
 
-    Ok((cmd, args))
-}
+async fn cancel_trace(state: &AppState, trace_id: &str) -> Result<(), String> {
+    let running_trace = state.running_traces.lock().expect("Failed to acquire mutex lock for stopping trace").remove(trace_id);
+    let mut running_trace = match running_trace {
+        Some(running_trace) => running_trace,
+        None => return Err("Trace not found".to_string()),
+    };
 
-fn parse_traceroute_line(line: &str) -> Option<HopData> {
-    // Windows tracert format: " 1    <time> ms    <time> ms    <time> ms     <ip>"
-    // Or: " 1    *        *        *     Request timed out."
-    // Or: "10    81 ms    68 ms    62 ms  dns.google [8.8.8.8]" (domain [ip] format)
-    // Unix traceroute format: " 1  <ip> (<ip>)  <time> ms  <time> ms  <time> ms"
-    
-    // Trim leading whitespace
-    let line = line.trim();
-    
-    // Skip empty lines and header lines
-    if line.is_empty() || 
-       line.starts_with("Tracing") || 
-       line.starts_with("over a maximum") || 
-       line.starts_with("Trace complete") {
-        return None;
+    running_trace.cancelled.store(true, Ordering::SeqCst);
+    running_trace.cancel_notify.notify_one();
+
+    // Aborting straight away could drop the task before it kills the child, wait for it to finish instead
+    if tokio::time::timeout(STOP_TRACE_GRACE, &mut running_trace.handle).await.is_err() {
+        tracing::warn!("[Rust] [TRACE] Trace {} did not stop within {:?}, aborting task", trace_id, STOP_TRACE_GRACE);
+        running_trace.handle.abort();
+        // The aborted task can no longer record its own outcome
+        record_trace_outcome(
+            &state.trace_outcomes,
+            trace_id,
+            "cancelled",
+            None,
+            Some("Trace aborted after it did not stop in time".to_string()),
+        );
     }
-    
-    // Try to extract hop number
-    let parts: Vec<&str> = line.split_whitespace().collect();
-    if parts.is_empty() {
-        return None;
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn stop_trace(trace_id: String, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    cancel_trace(&state, &trace_id).await
+}
+
+#[derive(Serialize)]
+struct StopTraceResult {
+    trace_id: String,
+    stopped: bool,
+    error: Option<String>,
+}
+
+// Cancel several traces in one call, e.g. when a dashboard of traces is closed
+#[tauri::command]
+async fn stop_traces(ids: Vec<String>, state: tauri::State<'_, AppState>) -> Result<Vec<StopTraceResult>, String> {
+    Ok(cancel_traces(state.inner(), ids).await)
+}
+
+async fn cancel_traces(state: &AppState, ids: Vec<String>) -> Vec<StopTraceResult> {
+    futures::future::join_all(ids.into_iter().map(|trace_id| async move {
+        let outcome = cancel_trace(state, &trace_id).await;
+        StopTraceResult {
+            trace_id,
+            stopped: outcome.is_ok(),
+            error: outcome.err(),
+        }
+    }))
+    .await
+}
+
+// Highest TTL probe_ttl accepts
+const MAX_PROBE_TTL: u32 = 64;
+
+// Probe a single hop, e.g. to repeatedly check one intermediate router
+#[tauri::command]
+async fn probe_ttl(target: String, ttl: u32, state: tauri::State<'_, AppState>) -> Result<HopData, String> {
+    if !is_valid_target(&target) {
+        return Err(format!("Invalid target format: {}", target));
     }
-    
-    // Extract hop number
-    let hop_num = parts[0].parse::<u32>().ok()?;
-    
-    // Check if it's a timeout line - specifically look for "Request timed out"
-    if line.contains("Request timed out") {
-        return Some(HopData {
-            hop: hop_num,
-            host: None,
-            ip: None,
-            latencies: vec![None, None, None], // Three timeouts
-            avg_latency: None,
-            status: "timeout".to_string(),
-            geo: None,
-        });
+    if !(1..=MAX_PROBE_TTL).contains(&ttl) {
+        return Err(format!("TTL must be between 1 and {}", MAX_PROBE_TTL));
     }
-    
-    // Parse based on OS format
+
+    let options = probe_ttl_options(ttl);
+    let (cmd, args) = prepare_trace_command(&target, &options)?;
+    tracing::info!("[Rust] [TRACE] probe_ttl cmd='{}' args='{:?}'", cmd, args);
+
+    let mut cmd_builder = Command::new(&cmd);
+    cmd_builder
+        .args(&args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
     #[cfg(windows)]
     {
-        // Windows format: "1    <1 ms    <1 ms    <1 ms    192.168.1.1"
-        // Or: "1    1 ms    1 ms    1 ms    192.168.1.1"
-        // Or: "1    *        *        *     Request timed out."
-        // Or: "10    81 ms    68 ms    62 ms  dns.google [8.8.8.8]" (special domain [ip] format)
-        
-        let mut latencies = Vec::new();
-        let mut ip_part = None;
-        let mut host_part = None;
-        
-        let mut i = 1; // Start after hop number
-        
-        // Process up to 3 latency values - look for latency patterns
-        let mut latency_count = 0;
-        while i < parts.len() && latency_count < 3 {
-            let part = parts[i];
-            
-            // Look for patterns: number followed by "ms", or "<number" followed by "ms", or "*"
-            if part == "*" {
-                // Timeout marker
-                latencies.push(None);
-                latency_count += 1;
-                i += 1;
-            } else if part.starts_with('<') && i + 1 < parts.len() && parts[i+1] == "ms" {
-                // Pattern: "<1" "ms"
-                let time_str = part.strip_prefix("<").unwrap_or(part);
-                if let Ok(time) = time_str.parse::<f64>() {
-                    latencies.push(Some(time));
-                } else {
-                    latencies.push(None);
-                }
-                latency_count += 1;
-                i += 2; // Skip both the number and "ms"
-            } else if i + 1 < parts.len() && parts[i+1] == "ms" {
-                // Pattern: "1" "ms"
-                if let Ok(time) = part.parse::<f64>() {
-                    latencies.push(Some(time));
-                } else {
-                    latencies.push(None);
-                }
-                latency_count += 1;
-                i += 2; // Skip both the number and "ms"
-            } else {
-                // Not a latency pattern, move to next
-                i += 1;
-            }
-        }
-        
-        // The remaining parts should contain IP/address
-        // Look for the IP address at the end of the line
-        for j in i..parts.len() {
-            let part = parts[j];
-            
-            // Check for the special "domain [ip]" format (e.g., "dns.google [8.8.8.8]")
-            if part.starts_with('[') && part.ends_with(']') {
-                // Extract IP from [ip] format
-                let inner = &part[1..part.len()-1]; // Remove [ and ]
-                if is_valid_ipv4_format(inner) {
-                    ip_part = Some(inner.to_string());
-                    // If previous part looks like a hostname, capture it
-                    if j > 0 && !parts[j-1].ends_with("ms") && parts[j-1] != "*" {
-                        host_part = Some(parts[j-1].to_string());
-                    }
-                    break;
-                }
-            }
-            // If it looks like an IP (contains dots and valid format)
-            else if part.contains('.') && is_valid_ipv4_format(part) {
-                ip_part = Some(part.to_string());
-                break;
-            }
+        // CREATE_NO_WINDOW
+        cmd_builder.creation_flags(0x08000000);
+    }
+
+    let _active_child = ActiveChildGuard::new(state.active_children.clone());
+    let output = match tokio::time::timeout(probe_ttl_timeout(ttl, &options), cmd_builder.output()).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => return Err(format!("Failed to start {}: {}", cmd, e)),
+        Err(_) => return Err(format!("Probe of hop {} timed out", ttl)),
+    };
+
+    // tracert can't start at a given TTL, so its output also contains the hops before it
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let mut hop_data = stdout
+        .lines()
+        .chain(stderr.lines())
+        .filter_map(parse_traceroute_line)
+        .find(|hop| hop.hop == ttl)
+        .ok_or_else(|| format!("No response parsed for hop {}", ttl))?;
+
+    hop_data.received_at = chrono::Utc::now().to_rfc3339();
+    hop_data.avg_latency = hop_data.avg_latency.map(|ms| normalize_latency(ms, DEFAULT_LATENCY_PRECISION));
+    enrich_hop(&mut hop_data, false, None).await;
+    Ok(hop_data)
+}
+
+// traceroute probes only the TTL (-f ttl -m ttl), tracert has no first hop and runs up to it (-h ttl)
+fn probe_ttl_options(ttl: u32) -> TraceOptions {
+    TraceOptions {
+        max_hops: Some(ttl),
+        first_hop: if cfg!(windows) { None } else { Some(ttl) },
+        resolve_dns: Some(false),
+        ..Default::default()
+    }
+}
+
+// Longest a probe_ttl run may take. tracert prints every hop before the TTL,
+// each of which may time out on all of its probes.
+fn probe_ttl_timeout(ttl: u32, options: &TraceOptions) -> tokio::time::Duration {
+    let hops_probed = if cfg!(windows) { ttl } else { 1 };
+    let probe_time = tokio::time::Duration::from_millis(
+        options.timeout_ms.unwrap_or(DEFAULT_PROBE_TIMEOUT_MS)
+            .saturating_mul(expected_probes(options) as u64)
+            .saturating_mul(hops_probed as u64),
+    );
+    probe_time + TRACE_IDLE_SLACK
+}
+
+// Parallel ping processes allowed per ping_hops call
+const PING_CONCURRENCY: usize = 8;
+const MAX_PING_COUNT: u32 = 20;
+
+#[derive(Serialize)]
+struct PingResult {
+    ip: String,
+    sent: u32,
+    received: u32,
+    loss_percent: f64,
+    min_ms: Option<f64>,
+    avg_ms: Option<f64>,
+    max_ms: Option<f64>,
+    // Private address left out because skip_private was set
+    skipped: bool,
+    error: Option<String>,
+}
+
+impl PingResult {
+    fn failed(ip: String, skipped: bool, error: String) -> Self {
+        PingResult {
+            ip,
+            sent: 0,
+            received: 0,
+            loss_percent: 100.0,
+            min_ms: None,
+            avg_ms: None,
+            max_ms: None,
+            skipped,
+            error: Some(error),
         }
-        
-        // Calculate average latency if we have valid samples
-        let valid_latencies: Vec<f64> = latencies.iter()
-            .filter_map(|opt| *opt)
-            .collect();
-            
-        let avg_latency = if !valid_latencies.is_empty() {
-            Some((valid_latencies.iter().sum::<f64>() / valid_latencies.len() as f64).round())
-        } else {
-            None
-        };
-            
-        Some(HopData {
-            hop: hop_num,
-            host: host_part,
-            ip: ip_part,
-            latencies: latencies, // Store the full array of latency values
-            avg_latency,
-            status: if !valid_latencies.is_empty() { "success".to_string() } else { "timeout".to_string() },
-            geo: None,
-        })
     }
-    
-    #[cfg(unix)]
-    {
-        // Unix format: "1  192.168.1.1 (192.168.1.1)  1.234 ms  2.345 ms  2.346 ms"
-        let mut latencies = Vec::new();
-        let mut ip_part = None;
-        let mut host_part = None;
-        
-        let mut iter = parts.iter().skip(1); // Skip hop number
-        
-        // First parts might be IP and/or hostname in parentheses
-        if let Some(ip_or_host) = iter.next() {
-            if !ip_or_host.contains("ms") && !ip_or_host.starts_with('(') {
-                // This is likely the IP or host
-                if let Some(next_part) = iter.next() {
-                    if next_part.starts_with('(') && next_part.ends_with(')') {
-                        // Format is host (ip) or ip (ip)
-                        host_part = Some((*ip_or_host).to_string());
-                        let clean_ip = next_part.strip_prefix('(')?.strip_suffix(')')?;
-                        ip_part = Some(clean_ip.to_string());
-                    } else {
-                        // Just IP
-                        ip_part = Some((*ip_or_host).to_string());
-                        // Check if next part is in parentheses (hostname)
-                        if next_part.starts_with('(') && next_part.ends_with(')') {
-                            let clean_host = next_part.strip_prefix('(')?.strip_suffix(')')?;
-                            host_part = Some(clean_host.to_string());
-                            iter.next(); // Skip the parenthesized part
-                        }
-                    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct PingStats {
+    sent: u32,
+    received: u32,
+    min_ms: Option<f64>,
+    avg_ms: Option<f64>,
+    max_ms: Option<f64>,
+}
+
+// Refresh latencies for hops of a finished trace by pinging each address.
+// Results come back in the same order as ips.
+#[tauri::command]
+async fn ping_hops(
+    ips: Vec<String>,
+    count: u32,
+    skip_private: Option<bool>,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<PingResult>, String> {
+    if !(1..=MAX_PING_COUNT).contains(&count) {
+        return Err(format!("Ping count must be between 1 and {}", MAX_PING_COUNT));
+    }
+    let skip_private = skip_private.unwrap_or(false);
+    let active_children = state.active_children.clone();
+
+    use futures::StreamExt;
+    let results = futures::stream::iter(ips)
+        .map(|ip| {
+            let active_children = active_children.clone();
+            async move {
+                let addr = match ip.parse::<std::net::IpAddr>() {
+                    Ok(addr) => addr,
+                    Err(_) => return PingResult::failed(ip, false, "Invalid IP address".to_string()),
+                };
+                if skip_private && is_private_addr(&addr) {
+                    return PingResult::failed(ip, true, "Skipped private address".to_string());
                 }
+                ping_ip(addr, count, active_children).await
             }
-        }
-        
-        // Look for latency values
-        for part in iter {
-            if part.ends_with("ms") {
-                let time_str = part.strip_suffix("ms")?;
-                let time = time_str.parse::<f64>().ok()?;
-                latencies.push(Some(time));
-            } else if *part == "*" {
-                latencies.push(None);
-            }
-        }
-        
-        // Calculate average latency if we have valid samples
-        let valid_latencies: Vec<f64> = latencies.iter()
-            .filter_map(|opt| *opt)
-            .collect();
-        
-        let avg_latency = if !valid_latencies.is_empty() {
-            Some((valid_latencies.iter().sum::<f64>() / valid_latencies.len() as f64).round())
-        } else {
-            None
-        };
-        
-        Some(HopData {
-            hop: hop_num,
-            host: host_part,
-            ip: ip_part,
-            latencies: latencies, // Store the full array of latency values
-            avg_latency,
-            status: if !valid_latencies.is_empty() { "success".to_string() } else { "timeout".to_string() },
-            geo: None,
         })
+        .buffered(PING_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+
+    Ok(results)
+}
+
+fn is_private_addr(addr: &std::net::IpAddr) -> bool {
+    match addr {
+        std::net::IpAddr::V4(v4) => v4.is_private() || v4.is_loopback() || v4.is_link_local(),
+        std::net::IpAddr::V6(v6) => is_private_ipv6(v6),
     }
 }
 
-// Helper function to validate IPv4 format
-fn is_valid_ipv4_format(s: &str) -> bool {
-    let parts: Vec<&str> = s.split('.').collect();
-    if parts.len() != 4 {
-        return false;
+// How long ping waits for each reply, so an unanswered echo can't hold up the sweep
+const PING_REPLY_TIMEOUT_MS: u64 = 1000;
+// Extra time given to ping on top of its own intervals and reply timeout
+const PING_DEADLINE_SLACK: tokio::time::Duration = tokio::time::Duration::from_secs(2);
+
+fn prepare_ping_command(addr: std::net::IpAddr, count: u32) -> (String, Vec<String>) {
+    let ip = addr.to_string();
+    if cfg!(windows) {
+        // -w is in milliseconds
+        let args = vec!["-n".to_string(), count.to_string(), "-w".to_string(), PING_REPLY_TIMEOUT_MS.to_string(), ip];
+        return ("ping".to_string(), args);
     }
-    
-    for part in parts {
-        if let Ok(num) = part.parse::<u8>() {
-            if num > 255 {
-                return false;
+
+    // macOS ping is IPv4 only, like its traceroute, and takes -W in milliseconds.
+    // iputils and BusyBox take it in seconds.
+    let is_macos = cfg!(target_os = "macos");
+    let cmd = if is_macos && addr.is_ipv6() { "ping6" } else { "ping" };
+    let reply_timeout = if is_macos {
+        PING_REPLY_TIMEOUT_MS.to_string()
+    } else {
+        PING_REPLY_TIMEOUT_MS.div_ceil(1000).to_string()
+    };
+    let args = vec!["-c".to_string(), count.to_string(), "-W".to_string(), reply_timeout, ip];
+    (cmd.to_string(), args)
+}
+
+// Echo requests go out a second apart, the last one then waits at most PING_REPLY_TIMEOUT_MS
+fn ping_deadline(count: u32) -> tokio::time::Duration {
+    tokio::time::Duration::from_secs(count.saturating_sub(1) as u64)
+        + tokio::time::Duration::from_millis(PING_REPLY_TIMEOUT_MS)
+        + PING_DEADLINE_SLACK
+}
+
+async fn ping_ip(addr: std::net::IpAddr, count: u32, active_children: Arc<AtomicUsize>) -> PingResult {
+    let ip = addr.to_string();
+    let (cmd, args) = prepare_ping_command(addr, count);
+
+    let mut cmd_builder = Command::new(&cmd);
+    cmd_builder
+        .args(&args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true);
+
+    #[cfg(windows)]
+    {
+        // CREATE_NO_WINDOW
+        cmd_builder.creation_flags(0x08000000);
+    }
+
+    let deadline = ping_deadline(count);
+    let _active_child = ActiveChildGuard::new(active_children);
+    let output = match tokio::time::timeout(deadline, cmd_builder.output()).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => return PingResult::failed(ip, false, format!("Failed to start ping: {}", e)),
+        Err(_) => return PingResult::failed(ip, false, "Ping timed out".to_string()),
+    };
+
+    // ping exits non-zero when nothing answered, the summary is still printed
+    match parse_ping_output(&String::from_utf8_lossy(&output.stdout)) {
+        Some(stats) => PingResult {
+            ip,
+            sent: stats.sent,
+            received: stats.received,
+            loss_percent: if stats.sent > 0 {
+                (stats.sent - stats.received.min(stats.sent)) as f64 * 100.0 / stats.sent as f64
+            } else {
+                100.0
+            },
+            min_ms: stats.min_ms,
+            avg_ms: stats.avg_ms,
+            max_ms: stats.max_ms,
+            skipped: false,
+            error: None,
+        },
+        None => PingResult::failed(ip, false, "Could not parse ping output".to_string()),
+    }
+}
+
+// Parse the summary of Windows ping:
+//     Packets: Sent = 4, Received = 4, Lost = 0 (0% loss),
+//     Minimum = 14ms, Maximum = 16ms, Average = 15ms
+// and of iputils / macOS / BusyBox ping:
+//     4 packets transmitted, 4 received, 0% packet loss, time 3004ms
+//     rtt min/avg/max/mdev = 14.1/15.2/16.3/0.8 ms
+fn parse_ping_output(output: &str) -> Option<PingStats> {
+    let mut stats: Option<PingStats> = None;
+
+    for line in output.lines() {
+        let line = line.trim();
+
+        if line.contains("Sent =") {
+            let entry = stats.get_or_insert_with(PingStats::default);
+            entry.sent = number_after(line, "Sent =")? as u32;
+            entry.received = number_after(line, "Received =")? as u32;
+        } else if line.contains("Minimum =") {
+            let entry = stats.get_or_insert_with(PingStats::default);
+            entry.min_ms = number_after(line, "Minimum =");
+            entry.max_ms = number_after(line, "Maximum =");
+            entry.avg_ms = number_after(line, "Average =");
+        } else if line.contains("packets transmitted") {
+            let entry = stats.get_or_insert_with(PingStats::default);
+            let mut parts = line.split(',');
+            entry.sent = parts.next()?.split_whitespace().next()?.parse().ok()?;
+            entry.received = parts.next()?.split_whitespace().next()?.parse().ok()?;
+        } else if line.contains("min/avg/max") {
+            let entry = stats.get_or_insert_with(PingStats::default);
+            let values: Vec<f64> = line
+                .split('=')
+                .nth(1)?
+                .trim()
+                .trim_end_matches("ms")
+                .split('/')
+                .filter_map(|value| value.trim().parse().ok())
+                .collect();
+            if values.len() >= 3 {
+                entry.min_ms = Some(values[0]);
+                entry.avg_ms = Some(values[1]);
+                entry.max_ms = Some(values[2]);
             }
-        } else {
-            return false;
         }
     }
-    true
+
+    stats
 }
 
-fn setup_logging() -> Result<(), Box<dyn std::error::Error>> {
-    use tracing_subscriber::{
-        fmt,
-        layer::SubscriberExt,
-        util::SubscriberInitExt,
-        EnvFilter,
-    };
-    use tracing_appender::rolling;
-    
-    // Get app data directory
-    let app_data_dir = directories::BaseDirs::new()
-        .map(|dirs| dirs.data_dir().join("TraceRT"))
-        .or_else(|| {
-            std::env::current_dir().ok().map(|dir| dir.join("data"))
-        })
-        .unwrap_or_else(|| std::path::PathBuf::from("."));
-    
-    // Ensure log directory exists
-    let log_dir = app_data_dir.join("logs");
-    std::fs::create_dir_all(&log_dir)?;
+fn number_after(line: &str, key: &str) -> Option<f64> {
+    let rest = line[line.find(key)? + key.len()..].trim_start();
+    let end = rest
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(rest.len());
+    rest[..end].parse().ok()
+}
+
+
+fn is_valid_target(target: &str) -> bool {
+    // Basic validation to prevent command injection
+    // Allow alphanumeric, dots, hyphens, colons (for IPv6), and underscores
+    // Also allow basic domain names
     
-    // Create daily rolling file appender
-    let file_appender = rolling::daily(&log_dir, "tracert.log");
+    // Check for potentially dangerous characters
+    if target.contains(' ') || target.contains('&') || target.contains('|') || 
+       target.contains(';') || target.contains('`') || target.contains('$') ||
+       target.contains('(') || target.contains(')') || target.contains('<') ||
+       target.contains('>') || target.contains('"') || target.contains('\'') {
+        return false;
+    }
     
-    let pid = std::process::id();
-    // Use system time instead of local offset since there are API issues
-    let file_layer = fmt::layer()
-        .with_ansi(false)
-        .with_writer(file_appender)
-        .with_filter(EnvFilter::from_default_env().add_directive("trace_rt=debug".parse()?));
-        
-    let console_layer = fmt::layer()
-        .with_writer(std::io::stderr)
-        .with_filter(EnvFilter::from_default_env().add_directive("trace_rt=debug".parse()?));
+    // A leading dash would be read as an option by traceroute/tracert
+    if target.starts_with('-') {
+        return false;
+    }
     
-    tracing_subscriber::registry()
-        .with(file_layer)
-        .with(console_layer)
-        .try_init()
-        .map_err(|e| Box::new(e) as Box<dyn std::error::Error>)?;
-        
-    tracing::info!("[Rust] [LIFECYCLE] App starting, PID={}", pid);
-    tracing::info!("[Rust] [LIFECYCLE] Log file located at: {:?}", log_dir.join("tracert.log"));
+    // Check for valid IP or domain format
+    let is_ip = target.parse::<std::net::IpAddr>().is_ok();
+    let is_domain = {
+        // Basic domain validation: letters, digits, dots, hyphens
+        // Must contain at least one dot and not start/end with special chars
+        !target.is_empty() &&
+        target.len() <= 255 &&
+        target.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_') &&
+        target.starts_with(|c: char| c.is_ascii_alphanumeric()) &&
+        target.ends_with(|c: char| c.is_ascii_alphanumeric()) &&
+        target.chars().any(|c| c == '.') &&  // Must contain at least one dot
+        !target.contains("..")  // No double dots
+    };
     
-    Ok(())
+    is_ip || is_domain
 }
 
-fn setup_panic_hook() {
-    std::panic::set_hook(Box::new(|panic_info| {
-        let pid = std::process::id();
-        let backtrace = std::backtrace::Backtrace::capture();
-        
-        tracing::error!(
-            "[Rust] [LIFECYCLE] Application panicked PID={}\npanic info: {}\nbacktrace: {:?}",
-            pid,
-            panic_info,
-            backtrace
-        );
-        
-        eprintln!(
-            "[Rust] [LIFECYCLE] Application panicked PID={}\npanic info: {}\nbacktrace: {:?}",
-            pid,
-            panic_info,
-            backtrace
-        );
-    }));
+// Forward lookup through the system resolver
+async fn resolve_host(host: &str) -> Result<Vec<std::net::IpAddr>, String> {
+    let addrs = tokio::net::lookup_host((host, 0))
+        .await
+        .map_err(|e| format!("Failed to resolve {}: {}", host, e))?;
+
+    let mut ips: Vec<std::net::IpAddr> = Vec::new();
+    for addr in addrs {
+        if !ips.contains(&addr.ip()) {
+            ips.push(addr.ip());
+        }
+    }
+    Ok(ips)
 }
 
-fn setup_ctrlc_handler() {
-    ctrlc::set_handler(move || {
-        let pid = std::process::id();
-        tracing::info!("[Rust] [LIFECYCLE] Received Ctrl+C signal, PID={}", pid);
-        cleanup_lock_file();
-        std::process::exit(0);
-    }).expect("Error setting Ctrl+C handler");
+#[derive(Serialize)]
+struct DnsTiming {
+    host: String,
+    resolution_ms: f64,
+    addresses: Vec<String>,
+    // The host was already resolved earlier in this session, so the answer
+    // most likely came from the OS resolver cache rather than a cold lookup
+    likely_cached: bool,
 }
 
-fn main() {
-    // Setup logging first
-    if let Err(e) = setup_logging() {
-        eprintln!("Failed to setup logging: {}", e);
-        std::process::exit(1);
-    }
-    
-    // Setup panic hook
-    setup_panic_hook();
-    
-    // Setup Ctrl+C handler
-    setup_ctrlc_handler();
-    
-    // Get app data directory and setup single instance guard
-    let app_data_dir = directories::BaseDirs::new()
-        .map(|dirs| dirs.data_dir().join("TraceRT"))
-        .or_else(|| {
-            std::env::current_dir().ok().map(|dir| dir.join("data"))
-        })
-        .unwrap_or_else(|| std::path::PathBuf::from("."));
-    
-    std::fs::create_dir_all(&app_data_dir).expect("Failed to create app data directory");
-    
-    if let Err(e) = setup_single_instance_guard(&app_data_dir) {
-        tracing::error!("[Rust] Failed to setup single instance guard: {}", e);
-        std::process::exit(1);
+static MEASURED_DNS_HOSTS: Lazy<Mutex<std::collections::HashSet<String>>> = Lazy::new(|| Mutex::new(std::collections::HashSet::new()));
+
+#[tauri::command]
+async fn measure_dns(host: String) -> Result<DnsTiming, String> {
+    // Nothing is spawned here, so single-label names like "localhost" are fine too
+    if host.is_empty() || host.len() > 255 ||
+       !host.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_' || c == ':') {
+        return Err("Invalid host format".to_string());
     }
-    
-    let pid = std::process::id();
-    tracing::info!("[Rust] [LIFECYCLE] Setup complete, PID={}", pid);
-    
-    tauri::Builder::default()
-        .manage(AppState {
-            running_traces: Arc::new(Mutex::new(HashMap::new())),
-        })
-        .invoke_handler(tauri::generate_handler![
-            run_trace,
-            stop_trace,
-            log_debug,
-            log_info,
-            log_warn,
-            log_error,
-            geo_lookup,
-            download_geolite_db,
-        ])
-        .setup(|_app| {
-            tracing::info!("[Rust] [LIFECYCLE] App setup completed, PID={}", std::process::id());
-            Ok(())
-        })
-        .build(tauri::generate_context!())
-        .expect("Failed to build tauri app")
-        .run(|_app_handle, event| {
-            match event {
-                tauri::RunEvent::ExitRequested { .. } => {
-                    tracing::info!("[Rust] [LIFECYCLE] Exit requested, PID={}", std::process::id());
-                }
-                tauri::RunEvent::Ready => {
-                    tracing::info!("[Rust] [LIFECYCLE] App ready, PID={}", std::process::id());
-                }
-                tauri::RunEvent::WindowEvent { label: _, event: _, .. } => {}
-                _ => {}
-            }
-        });
-        
-    tracing::info!("[Rust] [LIFECYCLE] App shutting down, PID={}", pid);
-}
 
-// Function to enrich hop data with geolocation information
-async fn enrich_hops_with_geolocation(hops: Vec<HopData>) -> Vec<HopData> {
-    // Since geolocation is now handled in real-time as each hop is parsed,
-    // this function is no longer needed for geolocation enrichment.
-    // It's kept for backward compatibility but returns hops as-is.
-    tracing::debug!("[Rust] [GEO] Skipping bulk geolocation enrichment - handled in real-time per hop");
-    hops
+    let started = std::time::Instant::now();
+    let addrs = resolve_host(&host).await?;
+    let resolution_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+    let likely_cached = !MEASURED_DNS_HOSTS
+        .lock()
+        .expect("Failed to acquire DNS timing lock")
+        .insert(host.to_ascii_lowercase());
+
+    tracing::debug!("[Rust] [DNS] Resolved {} in {:.3} ms ({} addresses, likely cached: {})",
+                   host, resolution_ms, addrs.len(), likely_cached);
+
+    Ok(DnsTiming {
+        host,
+        resolution_ms,
+        addresses: addrs.iter().map(|addr| addr.to_string()).collect(),
+        likely_cached,
+    })
 }
 
-// Helper function to check if an IP is private
-fn is_private_ip(ip_str: &str) -> bool {
-    tracing::debug!("[Rust] [GEO] Checking if IP {} is private", ip_str);
-    
-    let is_private = ip_str.starts_with("10.") || 
-    ip_str.starts_with("192.168.") || 
-    (ip_str.starts_with("172.") && {
-        let parts: Vec<&str> = ip_str.split('.').collect();
-        if parts.len() > 1 {
-            if let Ok(second_octet) = parts[1].parse::<u8>() {
-                let is_private_range = (16..=31).contains(&second_octet);
-                tracing::debug!("[Rust] [GEO] 172.x.x.x second octet: {}, private range: {}", second_octet, is_private_range);
-                is_private_range
+// Prefer IPv4 when the target has A records, fall back to IPv6 when it only has AAAA.
+// Stays Auto (no flag, the tool decides) when the target can't be resolved here.
+async fn resolve_auto_family(target: &str) -> IpFamily {
+    if let Ok(addr) = target.parse::<std::net::IpAddr>() {
+        return if addr.is_ipv4() { IpFamily::V4 } else { IpFamily::V6 };
+    }
+
+    match resolve_host(target).await {
+        Ok(addrs) => {
+            if addrs.iter().any(|addr| addr.is_ipv4()) {
+                IpFamily::V4
+            } else if addrs.iter().any(|addr| addr.is_ipv6()) {
+                IpFamily::V6
             } else {
-                tracing::debug!("[Rust] [GEO] Failed to parse second octet for 172.x.x.x IP");
-                false
+                IpFamily::Auto
             }
-        } else {
-            false
         }
-    });
-    
-    tracing::debug!("[Rust] [GEO] IP {} is private: {}", ip_str, is_private);
-    is_private
-}
-
-// Internal function to perform geolocation lookup
-async fn geo_lookup_inner(ip: String) -> Result<GeoResult, String> {
-    tracing::debug!("[Rust] [GEO] Starting geolocation lookup for IP: {}", ip);
-    
-    // Check if it's a private IP - don't look up geolocation for private IPs
-    if ip.starts_with("10.") || 
-       ip.starts_with("192.168.") || 
-       (ip.starts_with("172.") && {
-           let parts: Vec<&str> = ip.split('.').collect();
-           if parts.len() > 1 {
-               let second_octet = parts[1].parse::<u8>().unwrap_or(0);
-               (16..=31).contains(&second_octet)
-           } else {
-               false
-           }
-       }) {
-        tracing::debug!("[Rust] [GEO] Skipping geolocation for private IP: {}", ip);
-        return Ok(GeoResult {
-            ip,
-            lat: None,
-            lng: None,
-            city: Some("Private/Internal".to_string()),
-            country: None,
-            country_code: None,
-        });
+        Err(e) => {
+            tracing::warn!("[Rust] [TRACE] Failed to resolve {} for auto family: {}", target, e);
+            IpFamily::Auto
+        }
     }
+}
 
-    let db = GEO_DB.as_ref().ok_or_else(|| {
-        tracing::warn!("[Rust] [GEO] Geolocation database not loaded");
-        "Geolocation database not loaded".to_string()
-    })?;
-    
-    let addr: std::net::IpAddr = ip.parse().map_err(|e| {
-        tracing::warn!("[Rust] [GEO] Invalid IP address {}: {}", ip, e);
-        "Invalid IP address".to_string()
-    })?;
+fn prepare_trace_command(target: &str, options: &TraceOptions) -> Result<(String, Vec<String>), String> {
+    let cmd;
+    let mut args = Vec::new();
 
-    use maxminddb::PathElement;
+    // A literal address can only be traced over its own family
+    match (target.parse::<std::net::IpAddr>(), options.family) {
+        (Ok(std::net::IpAddr::V6(_)), Some(IpFamily::V4)) => {
+            return Err("Cannot trace an IPv6 address with family v4".to_string());
+        }
+        (Ok(std::net::IpAddr::V4(_)), Some(IpFamily::V6)) => {
+            return Err("Cannot trace an IPv4 address with family v6".to_string());
+        }
+        _ => {}
+    }
 
-    let result = db.lookup(addr).map_err(|e| {
-        tracing::debug!("[Rust] [GEO] Geolocation lookup failed for {}: {}", ip, e);
-        e.to_string()
-    })?;
+    if let Some(source_port) = options.source_port {
+        if source_port == 0 {
+            return Err("Source port must be between 1 and 65535".to_string());
+        }
+        // --sport applies to the default UDP probes (and TCP), the trace never switches to ICMP
+        // probes (-I), where there is no port to fix
+        if cfg!(windows) {
+            return Err("tracert cannot set a source port".to_string());
+        }
+        if cfg!(target_os = "macos") {
+            return Err("macOS traceroute cannot set a source port".to_string());
+        }
+    }
 
-    let lat: Option<f64> = result.decode_path(&[
-        PathElement::Key("location"),
-        PathElement::Key("latitude"),
-    ]).map_err(|e| e.to_string())?;
+    if options.first_hop.is_some() && cfg!(windows) {
+        return Err("tracert cannot start at a given hop".to_string());
+    }
 
-    let lng: Option<f64> = result.decode_path(&[
-        PathElement::Key("location"),
-        PathElement::Key("longitude"),
-    ]).map_err(|e| e.to_string())?;
+    // Set command based on OS
+    #[cfg(windows)]
+    {
+        cmd = "tracert".to_string();
+        args.push("-d".to_string()); // Don't resolve addresses to names initially
+        
+        args.push("-h".to_string());
+        args.push(options.max_hops.unwrap_or(DEFAULT_MAX_HOPS).to_string());
+        
+        if let Some(timeout_ms) = options.timeout_ms {
+            args.push("-w".to_string());
+            args.push(timeout_ms.to_string());
+        }
+        
+        if options.resolve_dns.unwrap_or(false) {
+            // Remove -d flag if DNS resolution is requested
+            if let Some(pos) = args.iter().position(|x| x == "-d") {
+                args.remove(pos);
+            }
+        }
+        
+        match options.family {
+            Some(IpFamily::V4) => args.push("-4".to_string()),
+            Some(IpFamily::V6) => args.push("-6".to_string()),
+            _ => {}
+        }
+    }
+    
+    #[cfg(unix)]
+    {
+        // macOS traceroute is IPv4 only and has a separate traceroute6 binary
+        let is_macos = cfg!(target_os = "macos");
+        cmd = if is_macos && options.family == Some(IpFamily::V6) {
+            "traceroute6".to_string()
+        } else {
+            "traceroute".to_string()
+        };
+        
+        if !is_macos {
+            match options.family {
+                Some(IpFamily::V4) => args.push("-4".to_string()),
+                Some(IpFamily::V6) => args.push("-6".to_string()),
+                _ => {}
+            }
+        }
+        
+        if let Some(first_hop) = options.first_hop {
+            args.push("-f".to_string());
+            args.push(first_hop.to_string());
+        }
+        
+        args.push("-m".to_string());
+        args.push(options.max_hops.unwrap_or(DEFAULT_MAX_HOPS).to_string());
+        
+        if let Some(timeout_ms) = options.timeout_ms {
+            // Convert ms to seconds for traceroute
+            let timeout_sec = std::cmp::max((timeout_ms as f64 / 1000.0).ceil() as u64, 1);
+            args.push("-w".to_string());
+            args.push(timeout_sec.to_string());
+        }
+        
+        if let Some(probes) = options.probes_per_hop {
+            args.push("-q".to_string());
+            args.push(probes.to_string());
+        }
+        
+        if !options.resolve_dns.unwrap_or(true) {
+            args.push("-n".to_string()); // Skip reverse DNS lookup
+        }
+        
+        // Every probe leaves from this port. Only the source port is fixed: the destination
+        // port of the UDP probes still goes up by one per probe (33434, 33435, ...), which is
+        // how replies are matched to probes. --sport implies -N 1, so the probes go out one at a
+        // time and a trace takes longer than without it.
+        if let Some(source_port) = options.source_port {
+            args.push(format!("--sport={}", source_port));
+        }
+        
+        // End of options, the target can never be taken for a flag
+        args.push("--".to_string());
+    }
 
-    let city_name: Option<String> = result.decode_path(&[
-        PathElement::Key("city"),
-        PathElement::Key("names"),
-        PathElement::Key("en"),
-    ]).map_err(|e| e.to_string())?;
+    args.push(target.to_string());
 
-    let country_name: Option<String> = result.decode_path(&[
-        PathElement::Key("country"),
-        PathElement::Key("names"),
-        PathElement::Key("en"),
-    ]).map_err(|e| e.to_string())?;
+    // An explicit binary replaces the tool name, it takes the same arguments
+    if let Some(ref tool_path) = options.tool_path {
+        validate_tool_path(tool_path)?;
+        return Ok((tool_path.clone(), args));
+    }
 
-    let country_code: Option<String> = result.decode_path(&[
-        PathElement::Key("country"),
-        PathElement::Key("iso_code"),
-    ]).map_err(|e| e.to_string())?;
+    Ok((cmd, args))
+}
 
-    tracing::debug!("[Rust] [GEO] Successful lookup for {}: lat={:?}, lng={:?}, city={:?}, country={:?}",
-                   ip, lat, lng, city_name, country_name);
-    
-    Ok(GeoResult {
-        ip,
-        lat,
-        lng,
-        city: city_name,
-        country: country_name,
-        country_code,
-    })
+// File names toolPath may point at, with ".exe" on Windows
+const TRACE_TOOL_NAMES: [&str; 3] = ["traceroute", "traceroute6", "tracert"];
+
+// toolPath comes over IPC and is saved with the last options, so it may only select another
+// build of a trace tool, never run an arbitrary program
+fn validate_tool_path(tool_path: &str) -> Result<(), String> {
+    let path = Path::new(tool_path);
+    if !path.is_absolute() {
+        return Err(format!("Trace tool path must be absolute: {}", tool_path));
+    }
+
+    let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+    let lower = file_name.to_ascii_lowercase();
+    let tool_name = if cfg!(windows) { lower.strip_suffix(".exe").unwrap_or(&lower) } else { file_name };
+    if !TRACE_TOOL_NAMES.contains(&tool_name) {
+        return Err(format!(
+            "Trace tool must be named {}: {}",
+            TRACE_TOOL_NAMES.join(", "),
+            tool_path
+        ));
+    }
+
+    if !path.is_file() {
+        return Err(format!("Trace tool not found or not a file: {}", tool_path));
+    }
+    Ok(())
 }
 
-#[tauri::command]
-async fn download_geolite_db() -> Result<String, String> {
-    let app_data_dir = BaseDirs::new()
-        .map(|dirs| dirs.data_dir().join("TraceRT"))
-        .unwrap_or_else(|| std::path::PathBuf::from("./data"));
+// How long the trace tool gets to print its usage when checked for BusyBox
+const BUSYBOX_CHECK_TIMEOUT: tokio::time::Duration = tokio::time::Duration::from_secs(2);
+
+// BusyBox prints its banner in the usage text of every applet
+fn is_busybox_usage(output: &str) -> bool {
+    output.lines().any(|line| line.trim_start().starts_with("BusyBox v"))
+}
+
+// BusyBox traceroute has no --sport, it would only fail with its usage text once the trace runs
+async fn is_busybox_tool(cmd: &str) -> bool {
+    let output = Command::new(cmd)
+        .arg("--help")
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    match tokio::time::timeout(BUSYBOX_CHECK_TIMEOUT, output).await {
+        Ok(Ok(output)) => {
+            is_busybox_usage(&String::from_utf8_lossy(&output.stdout))
+                || is_busybox_usage(&String::from_utf8_lossy(&output.stderr))
+        }
+        _ => false,
+    }
+}
+
+const DEFAULT_LATENCY_PRECISION: u32 = 1;
+const MAX_LATENCY_PRECISION: u32 = 6;
+
+// Round a latency to the requested number of decimals so tracert's whole milliseconds and
+// traceroute's fractions display alike, without flattening sub-millisecond values to 0
+fn normalize_latency(ms: f64, precision: u32) -> f64 {
+    let mut decimals = precision.min(MAX_LATENCY_PRECISION) as i32;
+    if ms > 0.0 && ms < 1.0 {
+        // 3 significant figures: 0.234 needs 3 decimals, 0.0234 needs 4
+        let significant_decimals = 2 - ms.log10().floor() as i32;
+        decimals = decimals.max(significant_decimals);
+    }
+    let factor = 10f64.powi(decimals);
+    (ms * factor).round() / factor
+}
+
+// Output format of a trace tool
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Platform {
+    // tracert
+    Windows,
+    // traceroute, including BusyBox and macOS
+    Unix,
+}
+
+// Format of the trace tool this build runs
+const NATIVE_PLATFORM: Platform = if cfg!(windows) { Platform::Windows } else { Platform::Unix };
+
+fn parse_traceroute_line(line: &str) -> Option<HopData> {
+    parse_traceroute_line_as(line, NATIVE_PLATFORM)
+}
+
+fn parse_traceroute_line_as(line: &str, platform: Platform) -> Option<HopData> {
+    let mut hop_data = parse_hop_fields(line, platform)?;
+
+    // The tool prints the PTR name of the hop as it got it
+    if let Some(ref host) = hop_data.host {
+        if let std::borrow::Cow::Owned(escaped) = escape_control_chars(host) {
+            hop_data.host = Some(escaped);
+        }
+    }
+
+    // A hop that answered must have an address somewhere, dig it out of a malformed IP column
+    if hop_data.ip.is_none() && hop_data.latencies.iter().any(|latency| latency.is_some()) {
+        hop_data.ip = salvage_ip(line);
+        if let Some(ref ip) = hop_data.ip {
+            tracing::debug!("[Rust] [TRACE] Salvaged IP {} from hop {} line: {}", ip, hop_data.hop, line.trim());
+        }
+    }
+
+    // Answered probes the parser couldn't attribute (tracert prints one address per hop) came from the hop address
+    hop_data.probe_ips.resize(hop_data.latencies.len(), None);
+    for (probe_ip, latency) in hop_data.probe_ips.iter_mut().zip(&hop_data.latencies) {
+        if probe_ip.is_none() && latency.is_some() {
+            *probe_ip = hop_data.ip.clone();
+        }
+    }
+
+    Some(hop_data)
+}
+
+// Permissive address patterns, matches are confirmed by parsing them as real addresses
+static SALVAGE_IPV4: Lazy<regex::Regex> = Lazy::new(|| {
+    regex::Regex::new(r"(?:\d{1,3}\.){3}\d{1,3}").expect("Invalid IPv4 salvage pattern")
+});
+static SALVAGE_IPV6: Lazy<regex::Regex> = Lazy::new(|| {
+    regex::Regex::new(r"[0-9A-Fa-f]{0,4}(?::[0-9A-Fa-f]{0,4}){2,7}").expect("Invalid IPv6 salvage pattern")
+});
+
+fn salvage_ip(line: &str) -> Option<String> {
+    SALVAGE_IPV4
+        .find_iter(line)
+        .find_map(|m| m.as_str().parse::<std::net::Ipv4Addr>().ok().map(|addr| addr.to_string()))
+        .or_else(|| {
+            SALVAGE_IPV6
+                .find_iter(line)
+                .find_map(|m| m.as_str().parse::<std::net::Ipv6Addr>().ok().map(|addr| addr.to_string()))
+        })
+}
+
+fn parse_hop_fields(line: &str, platform: Platform) -> Option<HopData> {
+    // Windows tracert format: " 1    <time> ms    <time> ms    <time> ms     <ip>"
+    // Or: " 1    *        *        *     Request timed out."
+    // Or: "10    81 ms    68 ms    62 ms  dns.google [8.8.8.8]" (domain [ip] format)
+    // Unix traceroute format: " 1  <ip> (<ip>)  <time> ms  <time> ms  <time> ms"
     
-    // Create directory if it doesn't exist
-    fs::create_dir_all(&app_data_dir).await
-        .map_err(|e| format!("Failed to create directory: {}", e))?;
+    // Trim leading whitespace
+    let line = line.trim();
     
-    let db_path = app_data_dir.join("GeoLite2-City.mmdb");
+    // Skip empty lines and header lines
+    if line.is_empty() || 
+       line.starts_with("Tracing") || 
+       line.starts_with("over a maximum") || 
+       line.starts_with("Trace complete") {
+        return None;
+    }
     
-    // Check if file already exists
-    if db_path.exists() {
-        return Ok("Database already exists".to_string());
+    // Try to extract hop number
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    if parts.is_empty() {
+        return None;
     }
     
-    let url = "https://github.com/P3TERX/GeoLite.mmdb/raw/download/GeoLite2-City.mmdb";
+    // Extract hop number
+    let hop_num = parts[0].parse::<u32>().ok()?;
     
-    // Download the file
-    let response = reqwest::get(url)
-        .await
-        .map_err(|e| format!("Failed to download database: {}", e))?;
+    // Check if it's a timeout line - specifically look for "Request timed out"
+    if line.contains("Request timed out") {
+        return Some(HopData {
+            hop: hop_num,
+            host: None,
+            ip: None,
+            latencies: vec![None, None, None], // Three timeouts
+            avg_latency: None,
+            status: "timeout".to_string(),
+            geo: None,
+            annotation: None,
+            received_at: String::new(),
+            well_known: None,
+            probe_ips: Vec::new(),
+        });
+    }
     
-    if !response.status().is_success() {
-        return Err(format!("Download failed with status: {}", response.status()));
+    match platform {
+        Platform::Windows => parse_windows_hop(hop_num, line, &parts),
+        Platform::Unix => parse_unix_hop(hop_num, &parts),
     }
+}
+
+fn parse_windows_hop(hop_num: u32, line: &str, parts: &[&str]) -> Option<HopData> {
+    // Windows format: "1    <1 ms    <1 ms    <1 ms    192.168.1.1"
+    // Or: "1    1 ms    1 ms    1 ms    192.168.1.1"
+    // Or: "1    *        *        *     Request timed out."
+    // Or: "10    81 ms    68 ms    62 ms  dns.google [8.8.8.8]" (special domain [ip] format)
     
-    let content = response.bytes()
-        .await
-        .map_err(|e| format!("Failed to read response: {}", e))?;
+    let mut latencies = Vec::new();
+    let mut ip_part = None;
+    let mut host_part = None;
     
-    // Write to file
-    fs::write(&db_path, content).await
-        .map_err(|e| format!("Failed to save database: {}", e))?;
+    let mut i = 1; // Start after hop number
     
-    Ok(format!("Database downloaded to: {}", db_path.display()))
+    // Process up to 3 latency values - look for latency patterns
+    let mut latency_count = 0;
+    while i < parts.len() && latency_count < 3 {
+        let part = parts[i];
+        
+        // Look for patterns: number followed by "ms", or "<number" followed by "ms", or "*"
+        if part == "*" {
+            // Timeout marker
+            latencies.push(None);
+            latency_count += 1;
+            i += 1;
+        } else if part.starts_with('<') && i + 1 < parts.len() && parts[i+1] == "ms" {
+            // Pattern: "<1" "ms"
+            let time_str = part.strip_prefix("<").unwrap_or(part);
+            if let Ok(time) = time_str.parse::<f64>() {
+                latencies.push(Some(time));
+            } else {
+                latencies.push(None);
+            }
+            latency_count += 1;
+            i += 2; // Skip both the number and "ms"
+        } else if i + 1 < parts.len() && parts[i+1] == "ms" {
+            // Pattern: "1" "ms"
+            if let Ok(time) = part.parse::<f64>() {
+                latencies.push(Some(time));
+            } else {
+                latencies.push(None);
+            }
+            latency_count += 1;
+            i += 2; // Skip both the number and "ms"
+        } else {
+            // Not a latency pattern, move to next
+            i += 1;
+        }
+    }
+    
+    // The remaining parts should contain IP/address
+    // Look for the IP address at the end of the line
+    for j in i..parts.len() {
+        let part = parts[j];
+        
+        // Check for the special "domain [ip]" format (e.g., "dns.google [8.8.8.8]")
+        if part.starts_with('[') && part.ends_with(']') {
+            // Extract IP from [ip] format
+            let inner = &part[1..part.len()-1]; // Remove [ and ]
+            if is_valid_ipv4_format(inner) {
+                ip_part = Some(inner.to_string());
+                // If previous part looks like a hostname, capture it
+                if j > 0 && !parts[j-1].ends_with("ms") && parts[j-1] != "*" {
+                    host_part = Some(parts[j-1].to_string());
+                }
+                break;
+            }
+        }
+        // If it looks like an IP (contains dots and valid format)
+        else if part.contains('.') && is_valid_ipv4_format(part) {
+            ip_part = Some(part.to_string());
+            break;
+        }
+    }
+    
+    // Calculate average latency if we have valid samples
+    let valid_latencies: Vec<f64> = latencies.iter()
+        .filter_map(|opt| *opt)
+        .collect();
+        
+    let avg_latency = if !valid_latencies.is_empty() {
+        Some(valid_latencies.iter().sum::<f64>() / valid_latencies.len() as f64)
+    } else {
+        None
+    };
+    
+    // "3  10.0.0.1  reports: Destination host unreachable."
+    // The reporting router comes right before "reports:", which the latency scan skips over
+    if let Some(reason) = unreachable_reason(line) {
+        if ip_part.is_none() {
+            if let Some(pos) = parts.iter().position(|p| *p == "reports:") {
+                if pos > 1 {
+                    let reporter = parts[pos - 1].trim_start_matches('[').trim_end_matches(']');
+                    if is_valid_ipv4_format(reporter) {
+                        ip_part = Some(reporter.to_string());
+                    }
+                }
+            }
+        }
+        
+        return Some(HopData {
+            hop: hop_num,
+            host: host_part,
+            annotation: Some(match ip_part {
+                Some(ref ip) => format!("{} (reported by {})", reason, ip),
+                None => reason.to_string(),
+            }),
+            ip: ip_part,
+            latencies,
+            avg_latency,
+            status: "unreachable".to_string(),
+            geo: None,
+            received_at: String::new(),
+            well_known: None,
+            probe_ips: Vec::new(),
+        });
+    }
+        
+    Some(HopData {
+        hop: hop_num,
+        host: host_part,
+        ip: ip_part,
+        latencies: latencies, // Store the full array of latency values
+        avg_latency,
+        status: if !valid_latencies.is_empty() { "success".to_string() } else { "timeout".to_string() },
+        geo: None,
+        annotation: None,
+        received_at: String::new(),
+        well_known: None,
+        probe_ips: Vec::new(),
+    })
 }
 
-// Add a new event for individual hop updates
-#[tauri::command]
-async fn emit_hop_update(
-    app: tauri::AppHandle,
-    trace_id: &str,
-    hop_data: HopData
-) -> Result<(), String> {
-    tracing::debug!("[Rust] [TRACE] emit_hop_update called with trace_id: {}, hop: {}", trace_id, hop_data.hop);
+fn parse_unix_hop(hop_num: u32, parts: &[&str]) -> Option<HopData> {
+    // Unix format: "1  192.168.1.1 (192.168.1.1)  1.234 ms  2.345 ms  2.346 ms"
+    // Or: "1  _gateway (192.168.1.1)  0.345 ms  0.234 ms  0.198 ms" (GNU, resolved name)
+    // Or: "1  192.168.1.1  0.345 ms  0.234 ms  0.198 ms" (-n, and BusyBox without the copy)
+    // Or: "2  * * *" / "2  *  *  *" (BusyBox pads with two spaces)
+    // Or: "5  10.0.0.1 (10.0.0.1)  12.345 ms *  12.1 ms" (mixed probes)
+    // Or: "6  10.0.0.1 (10.0.0.1)  3.1 ms !H  3.2 ms !H  3.0 ms !H" (ICMP unreachable flags)
+    // Or: "7  a.example (10.0.0.1)  1.2 ms b.example (10.0.0.2)  1.5 ms  1.4 ms" (probes answered by different routers)
+    // Some builds glue the unit on: "0.345ms"
+    let mut latencies = Vec::new();
+    let mut probe_ips = Vec::new();
+    let mut ip_part: Option<String> = None;
+    let mut host_part: Option<String> = None;
+    let mut annotation: Option<String> = None;
+    // Router answering the probes that follow
+    let mut current_ip: Option<String> = None;
     
-    let event_payload = serde_json::json!({
-        "trace_id": trace_id,
-        "hop_data": hop_data
-    });
+    let tokens = &parts[1..]; // Skip hop number
+    let mut i = 0;
+    while i < tokens.len() {
+        let token = tokens[i];
+        
+        if token == "*" {
+            latencies.push(None);
+            probe_ips.push(None);
+            i += 1;
+        } else if let Ok(time) = token.parse::<f64>() {
+            // "1.234" "ms"
+            if tokens.get(i + 1) == Some(&"ms") {
+                latencies.push(Some(time));
+                probe_ips.push(current_ip.clone());
+                i += 2;
+            } else {
+                i += 1;
+            }
+        } else if let Some(time) = token.strip_suffix("ms").and_then(|t| t.parse::<f64>().ok()) {
+            // "1.234ms"
+            latencies.push(Some(time));
+            probe_ips.push(current_ip.clone());
+            i += 1;
+        } else if token.starts_with('!') {
+            if annotation.is_none() {
+                annotation = Some(unix_unreachable_flag(token));
+            }
+            i += 1;
+        } else if token.starts_with('(') && token.ends_with(')') {
+            // "(ip)" following the name or address of the responding router
+            let inner = &token[1..token.len() - 1];
+            if inner.parse::<std::net::IpAddr>().is_ok() {
+                current_ip = Some(inner.to_string());
+                if ip_part.is_none() {
+                    ip_part = Some(inner.to_string());
+                }
+            }
+            i += 1;
+        } else {
+            // Name or bare address of the router answering the next probes,
+            // only the first one is kept for the hop
+            current_ip = token.parse::<std::net::IpAddr>().ok().map(|_| token.to_string());
+            if ip_part.is_none() && host_part.is_none() {
+                if token.parse::<std::net::IpAddr>().is_ok() {
+                    ip_part = Some(token.to_string());
+                } else {
+                    host_part = Some(token.to_string());
+                }
+            }
+            i += 1;
+        }
+    }
     
-    let result = app.emit("hop:update", &event_payload)
-        .map_err(|e| format!("Failed to emit hop:update event: {}", e));
+    // A name that is just the address repeated isn't a hostname
+    if host_part.is_some() && host_part == ip_part {
+        host_part = None;
+    }
     
-    tracing::debug!("[Rust] [TRACE] emit 'hop:update' event -> {:?}", result);
-    result
+    // Calculate average latency if we have valid samples
+    let valid_latencies: Vec<f64> = latencies.iter()
+        .filter_map(|opt| *opt)
+        .collect();
+    
+    let avg_latency = if !valid_latencies.is_empty() {
+        Some(valid_latencies.iter().sum::<f64>() / valid_latencies.len() as f64)
+    } else {
+        None
+    };
+    
+    Some(HopData {
+        hop: hop_num,
+        host: host_part,
+        ip: ip_part,
+        latencies: latencies, // Store the full array of latency values
+        avg_latency,
+        status: if annotation.is_some() {
+            "unreachable".to_string()
+        } else if !valid_latencies.is_empty() {
+            "success".to_string()
+        } else {
+            "timeout".to_string()
+        },
+        geo: None,
+        annotation,
+        received_at: String::new(),
+        well_known: None,
+        probe_ips,
+    })
+}
+
+// traceroute appends "!H", "!N", ... after a probe time when an ICMP unreachable came back
+fn unix_unreachable_flag(flag: &str) -> String {
+    let reason = match flag {
+        "!H" => "Host unreachable".to_string(),
+        "!N" => "Network unreachable".to_string(),
+        "!P" => "Protocol unreachable".to_string(),
+        "!S" => "Source route failed".to_string(),
+        "!F" => "Fragmentation needed".to_string(),
+        "!X" => "Communication administratively prohibited".to_string(),
+        "!V" => "Host precedence violation".to_string(),
+        "!C" => "Precedence cutoff in effect".to_string(),
+        _ => match flag[1..].parse::<u32>() {
+            Ok(code) => format!("ICMP unreachable code {}", code),
+            Err(_) => "Unreachable".to_string(),
+        },
+    };
+    format!("{} ({})", reason, flag)
+}
+
+// Windows tracert reports ICMP unreachable replies in place of a normal hop address
+fn unreachable_reason(line: &str) -> Option<&'static str> {
+    if line.contains("Destination host unreachable") {
+        Some("Destination host unreachable")
+    } else if line.contains("Destination net unreachable") {
+        Some("Destination net unreachable")
+    } else {
+        None
+    }
+}
+
+// Pull the resolved target address out of the tool's header line:
+// "Tracing route to dns.google [8.8.8.8]", "Tracing route to 8.8.8.8 over a maximum of 30 hops",
+// "traceroute to dns.google (8.8.8.8), 30 hops max, 60 byte packets"
+fn parse_resolved_ip(line: &str) -> Option<String> {
+    let line = line.trim();
+    let rest = line.strip_prefix("Tracing route to ")
+        .or_else(|| line.strip_prefix("traceroute to "))
+        .or_else(|| line.strip_prefix("traceroute6 to "))?;
+
+    let candidate = match (rest.find(['[', '(']), rest.find([']', ')'])) {
+        (Some(start), Some(end)) if start < end => &rest[start + 1..end],
+        _ => rest.split_whitespace().next()?.trim_end_matches(','),
+    };
+
+    candidate.parse::<std::net::IpAddr>().ok().map(|ip| ip.to_string())
+}
+
+// Guess which tool produced some output, the compile-time OS says nothing about a pasted
+// transcript (or e.g. Linux traceroute output read under WSL). Headers decide outright,
+// otherwise hop lines vote by their column layout: tracert prints the probe times before the
+// address, traceroute prints the router first.
+fn detect_format(sample_lines: &[&str]) -> Platform {
+    let mut windows_votes = 0;
+    let mut unix_votes = 0;
+
+    for line in sample_lines {
+        let line = line.trim();
+        if line.starts_with("Tracing route to") || line.starts_with("over a maximum of") {
+            return Platform::Windows;
+        }
+        if line.starts_with("traceroute to") || line.starts_with("traceroute6 to") {
+            return Platform::Unix;
+        }
+
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() < 2 || parts[0].parse::<u32>().is_err() {
+            continue;
+        }
+        let windows_columns = line.contains("Request timed out")
+            || unreachable_reason(line).is_some()
+            || parts[1].starts_with('<')
+            || (parts[1].parse::<f64>().is_ok() && parts.get(2) == Some(&"ms"));
+        if windows_columns {
+            windows_votes += 1;
+        } else if parts[1] != "*" {
+            unix_votes += 1;
+        }
+    }
+
+    if windows_votes > unix_votes {
+        Platform::Windows
+    } else if unix_votes > windows_votes {
+        Platform::Unix
+    } else {
+        NATIVE_PLATFORM
+    }
+}
+
+// Target name as written in the tool's header line, e.g. "dns.google"
+fn parse_header_target(line: &str) -> Option<String> {
+    let line = line.trim();
+    let rest = line.strip_prefix("Tracing route to ")
+        .or_else(|| line.strip_prefix("traceroute to "))
+        .or_else(|| line.strip_prefix("traceroute6 to "))?;
+    rest.split_whitespace().next().map(|target| target.trim_end_matches(',').to_string())
+}
+
+// An unreachable report anywhere on the path means the destination was never reached.
+// Returns the error to report and whether the destination was reached.
+fn path_outcome(hops: &[HopData], resolved_ip: Option<&str>) -> (Option<String>, bool) {
+    let error = hops.iter()
+        .find(|hop| hop.status == "unreachable")
+        .map(|hop| format!("Hop {}: {}", hop.hop, hop.annotation.as_deref().unwrap_or("unreachable")));
+    let reached_destination = error.is_none() && hops.last().is_some_and(|last| {
+        last.status == "success" && match (resolved_ip, last.ip.as_deref()) {
+            (Some(resolved), Some(ip)) => resolved == ip,
+            _ => true,
+        }
+    });
+    (error, reached_destination)
+}
+
+// Parse a traceroute/tracert transcript captured elsewhere, e.g. pasted into a support case.
+// platform is "windows", "unix" or "auto" (detected from the text itself).
+#[tauri::command]
+async fn parse_trace_text(text: String, platform: String) -> Result<TraceResult, String> {
+    let platform = match platform.as_str() {
+        "windows" => Platform::Windows,
+        "unix" => Platform::Unix,
+        "auto" => detect_format(&text.lines().collect::<Vec<_>>()),
+        other => return Err(format!("Unknown platform: {}", other)),
+    };
+    tracing::info!("[Rust] [TRACE] Parsing {} bytes of trace text as {:?}", text.len(), platform);
+
+    let start_time = chrono::Utc::now().to_rfc3339();
+    let mut target: Option<String> = None;
+    let mut resolved_ip: Option<String> = None;
+    let mut raw_output = String::new();
+    let mut hops: Vec<HopData> = Vec::new();
+
+    for line in text.lines() {
+        raw_output.push_str(&escape_control_chars(line));
+        raw_output.push('\n');
+
+        if target.is_none() {
+            target = parse_header_target(line);
+        }
+        if resolved_ip.is_none() {
+            resolved_ip = parse_resolved_ip(line);
+        }
+
+        if let Some(mut hop_data) = parse_traceroute_line_as(line, platform) {
+            hop_data.avg_latency = hop_data.avg_latency.map(|ms| normalize_latency(ms, DEFAULT_LATENCY_PRECISION));
+            enrich_hop(&mut hop_data, false, None).await;
+            hops.push(hop_data);
+        }
+    }
+
+    if hops.is_empty() {
+        return Err("No hops found in the trace text".to_string());
+    }
+
+    let (error, reached_destination) = path_outcome(&hops, resolved_ip.as_deref());
+    Ok(TraceResult {
+        target: target.or_else(|| resolved_ip.clone()).unwrap_or_else(|| "unknown".to_string()),
+        resolved_ip,
+        hops,
+        // Pasted text is a single stream, there is no telling which lines went to stderr
+        stdout_raw: raw_output.clone(),
+        stderr_raw: String::new(),
+        raw_output,
+        start_time,
+        end_time: Some(chrono::Utc::now().to_rfc3339()),
+        reached_destination,
+        error,
+    })
+}
+
+// Helper function to validate IPv4 format
+fn is_valid_ipv4_format(s: &str) -> bool {
+    let parts: Vec<&str> = s.split('.').collect();
+    if parts.len() != 4 {
+        return false;
+    }
+    
+    for part in parts {
+        if let Ok(num) = part.parse::<u8>() {
+            if num > 255 {
+                return false;
+            }
+        } else {
+            return false;
+        }
+    }
+    true
+}
+
+fn setup_logging() -> Result<(), Box<dyn std::error::Error>> {
+    use tracing_subscriber::{
+        fmt,
+        layer::SubscriberExt,
+        util::SubscriberInitExt,
+        EnvFilter,
+    };
+    use tracing_appender::rolling;
+    
+    // Get app data directory
+    let app_data_dir = directories::BaseDirs::new()
+        .map(|dirs| dirs.data_dir().join("TraceRT"))
+        .or_else(|| {
+            std::env::current_dir().ok().map(|dir| dir.join("data"))
+        })
+        .unwrap_or_else(|| std::path::PathBuf::from("."));
+    
+    // Ensure log directory exists
+    let log_dir = app_data_dir.join("logs");
+    std::fs::create_dir_all(&log_dir)?;
+    
+    // Create daily rolling file appender
+    let file_appender = rolling::daily(&log_dir, "tracert.log");
+    
+    let pid = std::process::id();
+    // Use system time instead of local offset since there are API issues
+    let file_layer = fmt::layer()
+        .with_ansi(false)
+        .with_writer(file_appender)
+        .with_filter(EnvFilter::from_default_env().add_directive("trace_rt=debug".parse()?));
+        
+    let console_layer = fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_filter(EnvFilter::from_default_env().add_directive("trace_rt=debug".parse()?));
+    
+    tracing_subscriber::registry()
+        .with(file_layer)
+        .with(console_layer)
+        .try_init()
+        .map_err(|e| Box::new(e) as Box<dyn std::error::Error>)?;
+        
+    tracing::info!("[Rust] [LIFECYCLE] App starting, PID={}", pid);
+    tracing::info!("[Rust] [LIFECYCLE] Log file located at: {:?}", log_dir.join("tracert.log"));
+    
+    Ok(())
+}
+
+fn setup_panic_hook() {
+    std::panic::set_hook(Box::new(|panic_info| {
+        let pid = std::process::id();
+        let backtrace = std::backtrace::Backtrace::capture();
+        
+        tracing::error!(
+            "[Rust] [LIFECYCLE] Application panicked PID={}\npanic info: {}\nbacktrace: {:?}",
+            pid,
+            panic_info,
+            backtrace
+        );
+        
+        eprintln!(
+            "[Rust] [LIFECYCLE] Application panicked PID={}\npanic info: {}\nbacktrace: {:?}",
+            pid,
+            panic_info,
+            backtrace
+        );
+    }));
+}
+
+fn setup_ctrlc_handler() {
+    ctrlc::set_handler(move || {
+        let pid = std::process::id();
+        tracing::info!("[Rust] [LIFECYCLE] Received Ctrl+C signal, PID={}", pid);
+        cleanup_lock_file();
+        std::process::exit(0);
+    }).expect("Error setting Ctrl+C handler");
+}
+
+// Usage: NetTrace --json <target>
+// Prints each hop as a JSON line on stdout, logs keep going to stderr and the log file
+// Release builds use the windows subsystem and start without a console, so JSON lines printed
+// from cmd or PowerShell would go nowhere unless redirected. Attach to the console of the shell
+// that started us when stdout isn't already a file or pipe. The shell doesn't wait for a GUI
+// program, its prompt may come back before the output.
+#[cfg(windows)]
+fn attach_parent_console() {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetStdHandle(std_handle: u32) -> isize;
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+    const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+    const INVALID_HANDLE_VALUE: isize = -1;
+
+    // SAFETY: plain Win32 calls without pointer arguments
+    unsafe {
+        let stdout = GetStdHandle(STD_OUTPUT_HANDLE);
+        if stdout == 0 || stdout == INVALID_HANDLE_VALUE {
+            AttachConsole(ATTACH_PARENT_PROCESS);
+        }
+    }
+}
+
+fn run_headless(cli_args: &[String]) -> i32 {
+    #[cfg(windows)]
+    attach_parent_console();
+
+    let target = match cli_args.iter().find(|arg| !arg.starts_with("--")) {
+        Some(target) => target.clone(),
+        None => {
+            eprintln!("Usage: NetTrace --json <target>");
+            return 2;
+        }
+    };
+
+    if !is_valid_target(&target) {
+        eprintln!("Invalid target format: {}", target);
+        return 2;
+    }
+
+    let options = TraceOptions::default();
+    let (cmd, args) = match prepare_trace_command(&target, &options) {
+        Ok(prepared) => prepared,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Failed to start async runtime: {}", e);
+            return 1;
+        }
+    };
+
+    let trace_id = uuid::Uuid::new_v4().to_string();
+    tracing::info!("[Rust] [TRACE] Headless trace start target='{}' trace_id={}", target, trace_id);
+
+    runtime.block_on(init_geo_db());
+
+    let result = runtime.block_on(execute_trace_with_cancel(
+        None,
+        cmd,
+        args,
+        options,
+        Arc::new(Notify::new()),
+        trace_id,
+        Arc::new(AtomicUsize::new(0)),
+    ));
+
+    match result {
+        Ok(_) => 0,
+        Err(e) => {
+            eprintln!("Trace failed: {}", e);
+            1
+        }
+    }
+}
+
+fn main() {
+    // Setup logging first
+    if let Err(e) = setup_logging() {
+        eprintln!("Failed to setup logging: {}", e);
+        std::process::exit(1);
+    }
+    
+    // Setup panic hook
+    setup_panic_hook();
+    
+    // Headless mode: run a single trace without the GUI and exit
+    let cli_args: Vec<String> = std::env::args().skip(1).collect();
+    if cli_args.iter().any(|arg| arg == "--json") {
+        std::process::exit(run_headless(&cli_args));
+    }
+    
+    // Setup Ctrl+C handler
+    setup_ctrlc_handler();
+    
+    // Get app data directory and setup single instance guard
+    let app_data_dir = directories::BaseDirs::new()
+        .map(|dirs| dirs.data_dir().join("TraceRT"))
+        .or_else(|| {
+            std::env::current_dir().ok().map(|dir| dir.join("data"))
+        })
+        .unwrap_or_else(|| std::path::PathBuf::from("."));
+    
+    std::fs::create_dir_all(&app_data_dir).expect("Failed to create app data directory");
+    
+    if let Err(e) = setup_single_instance_guard(&app_data_dir) {
+        tracing::error!("[Rust] Failed to setup single instance guard: {}", e);
+        std::process::exit(1);
+    }
+    
+    let pid = std::process::id();
+    tracing::info!("[Rust] [LIFECYCLE] Setup complete, PID={}", pid);
+    
+    tauri::Builder::default()
+        .manage(AppState {
+            running_traces: Arc::new(Mutex::new(HashMap::new())),
+            active_children: Arc::new(AtomicUsize::new(0)),
+            trace_outcomes: Arc::new(Mutex::new(std::collections::VecDeque::new())),
+        })
+        .invoke_handler(tauri::generate_handler![
+            run_trace,
+            stop_trace,
+            stop_traces,
+            probe_ttl,
+            ping_hops,
+            parse_trace_text,
+            log_debug,
+            log_info,
+            log_warn,
+            log_error,
+            geo_lookup,
+            download_geolite_db,
+            clear_dns_cache,
+            geo_db_info,
+            check_geo_db_update,
+            measure_dns,
+            set_geo_db_path,
+            save_last_options,
+            load_last_options,
+            preload_geo_db,
+            get_active_child_count,
+            get_trace_outcome,
+            export_trace_geojson,
+            export_trace_kml,
+            estimate_routing_efficiency,
+            export_trace_compact_json,
+            import_trace_compact_json,
+            build_path_tree,
+        ])
+        .setup(|_app| {
+            tauri::async_runtime::spawn(init_geo_db());
+            tracing::info!("[Rust] [LIFECYCLE] App setup completed, PID={}", std::process::id());
+            Ok(())
+        })
+        .build(tauri::generate_context!())
+        .expect("Failed to build tauri app")
+        .run(|_app_handle, event| {
+            match event {
+                tauri::RunEvent::ExitRequested { .. } => {
+                    tracing::info!("[Rust] [LIFECYCLE] Exit requested, PID={}", std::process::id());
+                }
+                tauri::RunEvent::Ready => {
+                    tracing::info!("[Rust] [LIFECYCLE] App ready, PID={}", std::process::id());
+                }
+                tauri::RunEvent::WindowEvent { label: _, event: _, .. } => {}
+                _ => {}
+            }
+        });
+        
+    tracing::info!("[Rust] [LIFECYCLE] App shutting down, PID={}", pid);
+}
+
+// Function to enrich hop data with geolocation information
+async fn enrich_hops_with_geolocation(hops: Vec<HopData>) -> Vec<HopData> {
+    // Since geolocation is now handled in real-time as each hop is parsed,
+    // this function is no longer needed for geolocation enrichment.
+    // It's kept for backward compatibility but returns hops as-is.
+    tracing::debug!("[Rust] [GEO] Skipping bulk geolocation enrichment - handled in real-time per hop");
+    hops
+}
+
+// Labels for recognizable public resolvers and CDNs, bundled at build time.
+// A well_known_ips.json in the app data directory adds to or overrides the bundled entries.
+static WELL_KNOWN_IPS: Lazy<HashMap<std::net::IpAddr, String>> = Lazy::new(|| {
+    let mut labels = HashMap::new();
+    load_well_known_ips(include_str!("../resources/well_known_ips.json"), "bundled", &mut labels);
+
+    let user_path = BaseDirs::new()
+        .map(|dirs| dirs.data_dir().join("TraceRT"))
+        .unwrap_or_else(|| std::path::PathBuf::from("."))
+        .join("well_known_ips.json");
+    if let Ok(content) = std::fs::read_to_string(&user_path) {
+        load_well_known_ips(&content, &user_path.display().to_string(), &mut labels);
+    }
+
+    labels
+});
+
+fn load_well_known_ips(content: &str, source: &str, labels: &mut HashMap<std::net::IpAddr, String>) {
+    let entries: HashMap<String, String> = match serde_json::from_str(content) {
+        Ok(entries) => entries,
+        Err(e) => {
+            tracing::warn!("[Rust] [GEO] Ignoring invalid well-known IP table {}: {}", source, e);
+            return;
+        }
+    };
+
+    for (ip, label) in entries {
+        // Parsing normalizes IPv6 spellings so lookups match however the tool printed them
+        match ip.parse::<std::net::IpAddr>() {
+            Ok(addr) => {
+                labels.insert(addr, label);
+            }
+            Err(_) => tracing::warn!("[Rust] [GEO] Skipping invalid address '{}' in well-known IP table {}", ip, source),
+        }
+    }
+}
+
+fn well_known_label(ip: &str) -> Option<String> {
+    let addr: std::net::IpAddr = ip.parse().ok()?;
+    WELL_KNOWN_IPS.get(&addr).cloned()
+}
+
+// Enrich a single parsed hop with reverse DNS and geolocation data. `geo_fallback_url` is the
+// online geolocation endpoint to ask when the local database has no location, None when not allowed.
+async fn enrich_hop(hop_data: &mut HopData, resolve_dns: bool, geo_fallback_url: Option<&str>) {
+    let ip = match hop_data.ip.clone() {
+        Some(ip) => ip,
+        None => return,
+    };
+
+    if resolve_dns {
+        if let Ok(addr) = ip.parse::<std::net::IpAddr>() {
+            match hop_data.host.as_deref() {
+                // The tool already resolved this hop, remember the name for later traces
+                Some(host) if host != ip => dns_cache_insert(addr, Some(host.to_string())),
+                _ => {
+                    if let Some(name) = reverse_dns_lookup(addr).await {
+                        hop_data.host = Some(name);
+                    }
+                }
+            }
+        }
+    }
+
+    hop_data.well_known = well_known_label(&ip);
+
+    let mut geo_lookup = geo_lookup_inner(ip.clone()).await;
+
+    // Public address the local database knows nothing about, ask the online service if allowed
+    let missing_coords = match geo_lookup {
+        Ok(ref geo) => geo.lat.is_none() && geo.city.as_deref() != Some("Private/Internal"),
+        Err(_) => true,
+    };
+    if let Some(url_template) = geo_fallback_url.filter(|_| missing_coords) {
+        if let Some(online) = geo_lookup_online(&ip, url_template).await {
+            geo_lookup = Ok(online);
+        }
+    }
+
+    if let Ok(geo_result) = geo_lookup {
+        // Only set geo when lat/lng exist, don't set fake (0,0) coordinates
+        if let (Some(lat), Some(lng)) = (geo_result.lat, geo_result.lng) {
+            // Convert GeoResult to GeoLocation
+            hop_data.geo = Some(GeoLocation {
+                lat: Some(lat),
+                lng: Some(lng),
+                city: geo_result.city,
+                country: geo_result.country,
+                country_code: geo_result.country_code,
+                flag_emoji: geo_result.flag_emoji,
+            });
+        } else {
+            // Keep location text for private/internal but don't set geo coords
+            hop_data.geo = if geo_result.city.as_deref() == Some("Private/Internal") {
+                Some(GeoLocation {
+                    lat: None,
+                    lng: None,
+                    city: geo_result.city,
+                    country: geo_result.country,
+                    country_code: geo_result.country_code,
+                    flag_emoji: geo_result.flag_emoji,
+                })
+            } else {
+                None
+            };
+        }
+    }
+}
+
+// Limits concurrent outbound HTTP requests made on behalf of traces
+static OUTBOUND_REQUESTS: Lazy<tokio::sync::Semaphore> = Lazy::new(|| tokio::sync::Semaphore::new(4));
+
+// ipwho.is answers with {"success", "latitude", "longitude", "city", "country", "country_code"}
+const DEFAULT_GEO_FALLBACK_URL: &str = "https://ipwho.is/{ip}";
+// Minimum spacing between fallback requests to stay within the free tier
+const GEO_FALLBACK_MIN_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+const GEO_FALLBACK_CACHE_MAX_ENTRIES: usize = 1024;
+
+// Answers from the online service, None when it had no location for the address.
+// Bounded, the oldest answer is dropped first.
+#[derive(Default)]
+struct GeoFallbackCache {
+    entries: HashMap<std::net::IpAddr, Option<GeoResult>>,
+    order: std::collections::VecDeque<std::net::IpAddr>,
+}
+
+impl GeoFallbackCache {
+    fn get(&self, addr: &std::net::IpAddr) -> Option<Option<GeoResult>> {
+        self.entries.get(addr).cloned()
+    }
+
+    fn insert(&mut self, addr: std::net::IpAddr, result: Option<GeoResult>, max_entries: usize) {
+        if self.entries.insert(addr, result).is_some() {
+            return;
+        }
+        self.order.push_back(addr);
+        while self.order.len() > max_entries {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+}
+
+static GEO_FALLBACK_CACHE: Lazy<Mutex<GeoFallbackCache>> = Lazy::new(|| Mutex::new(GeoFallbackCache::default()));
+static GEO_FALLBACK_LAST_REQUEST: Lazy<tokio::sync::Mutex<Option<std::time::Instant>>> = Lazy::new(|| tokio::sync::Mutex::new(None));
+
+#[derive(Deserialize)]
+struct OnlineGeoResponse {
+    success: Option<bool>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    city: Option<String>,
+    country: Option<String>,
+    country_code: Option<String>,
+}
+
+fn geo_fallback_url() -> String {
+    load_settings().geo_fallback_url.unwrap_or_else(|| DEFAULT_GEO_FALLBACK_URL.to_string())
+}
+
+// Online geolocation for addresses missing from the local database, `url_template` has "{ip}"
+// replaced with the address. Request failures return None without caching so the hop keeps its
+// unknown location and can be retried later.
+async fn geo_lookup_online(ip: &str, url_template: &str) -> Option<GeoResult> {
+    let addr: std::net::IpAddr = ip.parse().ok()?;
+
+    if let Some(cached) = GEO_FALLBACK_CACHE.lock().expect("Failed to acquire geo fallback cache lock").get(&addr) {
+        return cached.clone();
+    }
+
+    let _permit = OUTBOUND_REQUESTS.acquire().await.ok()?;
+
+    // Space requests out, holding the lock while waiting keeps callers in line
+    {
+        let mut last_request = GEO_FALLBACK_LAST_REQUEST.lock().await;
+        if let Some(last) = *last_request {
+            let elapsed = last.elapsed();
+            if elapsed < GEO_FALLBACK_MIN_INTERVAL {
+                tokio::time::sleep(GEO_FALLBACK_MIN_INTERVAL - elapsed).await;
+            }
+        }
+        *last_request = Some(std::time::Instant::now());
+    }
+
+    let url = url_template.replace("{ip}", ip);
+
+    let client = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(5))
+        .build()
+        .ok()?;
+
+    let response = match client.get(&url).send().await {
+        Ok(response) if response.status().is_success() => response,
+        Ok(response) => {
+            tracing::warn!("[Rust] [GEO] Online geolocation for {} failed with status: {}", ip, response.status());
+            return None;
+        }
+        Err(e) => {
+            tracing::warn!("[Rust] [GEO] Online geolocation for {} failed: {}", ip, e);
+            return None;
+        }
+    };
+
+    let body: OnlineGeoResponse = match response.json().await {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::warn!("[Rust] [GEO] Invalid online geolocation response for {}: {}", ip, e);
+            return None;
+        }
+    };
+
+    let result = match (body.success.unwrap_or(true), body.latitude, body.longitude) {
+        (true, Some(lat), Some(lng)) => Some(GeoResult {
+            ip: ip.to_string(),
+            lat: Some(lat),
+            lng: Some(lng),
+            city: body.city,
+            country: body.country,
+            flag_emoji: body.country_code.as_deref().and_then(flag_emoji),
+            country_code: body.country_code,
+        }),
+        _ => None,
+    };
+
+    tracing::debug!("[Rust] [GEO] Online geolocation for {}: found={}", ip, result.is_some());
+    GEO_FALLBACK_CACHE.lock().expect("Failed to acquire geo fallback cache lock").insert(addr, result.clone(), GEO_FALLBACK_CACHE_MAX_ENTRIES);
+    result
+}
+
+// Cached PTR lookups, negative results are kept too so unresolvable hops aren't re-queried
+const DNS_CACHE_MAX_ENTRIES: usize = 1024;
+const DNS_POSITIVE_TTL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+const DNS_NEGATIVE_TTL: std::time::Duration = std::time::Duration::from_secs(60);
+const DNS_LOOKUP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(3);
+
+struct DnsCacheEntry {
+    name: Option<String>,
+    expires_at: std::time::Instant,
+}
+
+static DNS_CACHE: Lazy<Mutex<HashMap<std::net::IpAddr, DnsCacheEntry>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// Returns Some(entry) on a cache hit, where the entry itself may be a cached negative result
+fn dns_cache_get(addr: &std::net::IpAddr) -> Option<Option<String>> {
+    let mut cache = DNS_CACHE.lock().expect("Failed to acquire DNS cache lock");
+    match cache.get(addr) {
+        Some(entry) if entry.expires_at > std::time::Instant::now() => Some(entry.name.clone()),
+        Some(_) => {
+            cache.remove(addr);
+            None
+        }
+        None => None,
+    }
+}
+
+fn dns_cache_insert(addr: std::net::IpAddr, name: Option<String>) {
+    let now = std::time::Instant::now();
+    let ttl = if name.is_some() { DNS_POSITIVE_TTL } else { DNS_NEGATIVE_TTL };
+    let mut cache = DNS_CACHE.lock().expect("Failed to acquire DNS cache lock");
+
+    if cache.len() >= DNS_CACHE_MAX_ENTRIES && !cache.contains_key(&addr) {
+        cache.retain(|_, entry| entry.expires_at > now);
+        // Still full after dropping expired entries, evict the one closest to expiry
+        if cache.len() >= DNS_CACHE_MAX_ENTRIES {
+            if let Some(oldest) = cache.iter().min_by_key(|(_, entry)| entry.expires_at).map(|(ip, _)| *ip) {
+                cache.remove(&oldest);
+            }
+        }
+    }
+
+    cache.insert(addr, DnsCacheEntry { name, expires_at: now + ttl });
+}
+
+// Reverse DNS lookup for a hop address, consulting the cache before issuing a query
+async fn reverse_dns_lookup(addr: std::net::IpAddr) -> Option<String> {
+    if let Some(cached) = dns_cache_get(&addr) {
+        tracing::debug!("[Rust] [DNS] Cache hit for {}: {:?}", addr, cached);
+        return cached;
+    }
+
+    let name = query_ptr_record(addr).await;
+    tracing::debug!("[Rust] [DNS] Reverse lookup for {}: {:?}", addr, name);
+    dns_cache_insert(addr, name.clone());
+    name
+}
+
+// Issue the PTR query through the system nslookup, available on Windows, macOS and Linux
+async fn query_ptr_record(addr: std::net::IpAddr) -> Option<String> {
+    let mut cmd_builder = Command::new("nslookup");
+    cmd_builder
+        .arg(addr.to_string())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true);
+
+    #[cfg(windows)]
+    {
+        // CREATE_NO_WINDOW
+        cmd_builder.creation_flags(0x08000000);
+    }
+
+    let output = match tokio::time::timeout(DNS_LOOKUP_TIMEOUT, cmd_builder.output()).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => {
+            tracing::debug!("[Rust] [DNS] Failed to run nslookup for {}: {}", addr, e);
+            return None;
+        }
+        Err(_) => {
+            tracing::debug!("[Rust] [DNS] nslookup timed out for {}", addr);
+            return None;
+        }
+    };
+
+    parse_nslookup_ptr(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn parse_nslookup_ptr(output: &str) -> Option<String> {
+    // Unix: "8.8.8.8.in-addr.arpa	name = dns.google."
+    // Windows: "Name:    dns.google"
+    for line in output.lines() {
+        let line = line.trim();
+        let name = if let Some(pos) = line.find("name = ") {
+            &line[pos + "name = ".len()..]
+        } else if let Some(rest) = line.strip_prefix("Name:") {
+            rest
+        } else {
+            continue;
+        };
+
+        let name = name.trim().trim_end_matches('.');
+        if !name.is_empty() {
+            // PTR records are whatever the address owner put there
+            return Some(escape_control_chars(name).into_owned());
+        }
+    }
+    None
+}
+
+#[tauri::command]
+fn clear_dns_cache() -> usize {
+    let mut cache = DNS_CACHE.lock().expect("Failed to acquire DNS cache lock");
+    let cleared = cache.len();
+    cache.clear();
+    tracing::info!("[Rust] [DNS] Cleared {} cached reverse DNS entries", cleared);
+    cleared
+}
+
+// Helper function to check if an IP is private
+fn is_private_ip(ip_str: &str) -> bool {
+    tracing::debug!("[Rust] [GEO] Checking if IP {} is private", ip_str);
+    
+    let is_private = ip_str.starts_with("10.") || 
+    ip_str.starts_with("192.168.") || 
+    (ip_str.starts_with("172.") && {
+        let parts: Vec<&str> = ip_str.split('.').collect();
+        if parts.len() > 1 {
+            if let Ok(second_octet) = parts[1].parse::<u8>() {
+                let is_private_range = (16..=31).contains(&second_octet);
+                tracing::debug!("[Rust] [GEO] 172.x.x.x second octet: {}, private range: {}", second_octet, is_private_range);
+                is_private_range
+            } else {
+                tracing::debug!("[Rust] [GEO] Failed to parse second octet for 172.x.x.x IP");
+                false
+            }
+        } else {
+            false
+        }
+    });
+    
+    tracing::debug!("[Rust] [GEO] IP {} is private: {}", ip_str, is_private);
+    is_private
+}
+
+// IPv6 addresses that never appear in a geolocation database:
+// unique local (fc00::/7), link-local (fe80::/10), loopback and unspecified
+fn is_private_ipv6(addr: &std::net::Ipv6Addr) -> bool {
+    let first_segment = addr.segments()[0];
+    (first_segment & 0xfe00) == 0xfc00
+        || (first_segment & 0xffc0) == 0xfe80
+        || addr.is_loopback()
+        || addr.is_unspecified()
+}
+
+// ISO 3166-1 alpha-2 code to its flag, a pair of regional indicator symbols.
+// None unless the code is exactly two ASCII letters.
+fn flag_emoji(country_code: &str) -> Option<String> {
+    if country_code.len() != 2 || !country_code.bytes().all(|b| b.is_ascii_alphabetic()) {
+        return None;
+    }
+    country_code
+        .bytes()
+        .map(|b| char::from_u32(0x1F1E6 + u32::from(b.to_ascii_uppercase() - b'A')))
+        .collect()
+}
+
+// Internal function to perform geolocation lookup
+async fn geo_lookup_inner(ip: String) -> Result<GeoResult, String> {
+    tracing::debug!("[Rust] [GEO] Starting geolocation lookup for IP: {}", ip);
+    
+    // Check if it's a private IP - don't look up geolocation for private IPs
+    let is_private_v6 = matches!(
+        ip.parse::<std::net::IpAddr>(),
+        Ok(std::net::IpAddr::V6(v6)) if is_private_ipv6(&v6)
+    );
+    if is_private_v6 ||
+       ip.starts_with("10.") || 
+       ip.starts_with("192.168.") || 
+       (ip.starts_with("172.") && {
+           let parts: Vec<&str> = ip.split('.').collect();
+           if parts.len() > 1 {
+               let second_octet = parts[1].parse::<u8>().unwrap_or(0);
+               (16..=31).contains(&second_octet)
+           } else {
+               false
+           }
+       }) {
+        tracing::debug!("[Rust] [GEO] Skipping geolocation for private IP: {}", ip);
+        return Ok(GeoResult {
+            ip,
+            lat: None,
+            lng: None,
+            city: Some("Private/Internal".to_string()),
+            country: None,
+            country_code: None,
+            flag_emoji: None,
+        });
+    }
+
+    let db = current_geo_db().ok_or_else(|| {
+        tracing::warn!("[Rust] [GEO] Geolocation database not loaded");
+        "Geolocation database not loaded".to_string()
+    })?;
+    
+    let addr: std::net::IpAddr = ip.parse().map_err(|e| {
+        tracing::warn!("[Rust] [GEO] Invalid IP address {}: {}", ip, e);
+        "Invalid IP address".to_string()
+    })?;
+
+    use maxminddb::PathElement;
+
+    let result = db.reader.lookup(addr).map_err(|e| {
+        tracing::debug!("[Rust] [GEO] Geolocation lookup failed for {}: {}", ip, e);
+        e.to_string()
+    })?;
+
+    let lat: Option<f64> = result.decode_path(&[
+        PathElement::Key("location"),
+        PathElement::Key("latitude"),
+    ]).map_err(|e| e.to_string())?;
+
+    let lng: Option<f64> = result.decode_path(&[
+        PathElement::Key("location"),
+        PathElement::Key("longitude"),
+    ]).map_err(|e| e.to_string())?;
+
+    let city_name: Option<String> = result.decode_path(&[
+        PathElement::Key("city"),
+        PathElement::Key("names"),
+        PathElement::Key("en"),
+    ]).map_err(|e| e.to_string())?;
+
+    let country_name: Option<String> = result.decode_path(&[
+        PathElement::Key("country"),
+        PathElement::Key("names"),
+        PathElement::Key("en"),
+    ]).map_err(|e| e.to_string())?;
+
+    let country_code: Option<String> = result.decode_path(&[
+        PathElement::Key("country"),
+        PathElement::Key("iso_code"),
+    ]).map_err(|e| e.to_string())?;
+
+    tracing::debug!("[Rust] [GEO] Successful lookup for {}: lat={:?}, lng={:?}, city={:?}, country={:?}",
+                   ip, lat, lng, city_name, country_name);
+    
+    Ok(GeoResult {
+        ip,
+        lat,
+        lng,
+        city: city_name,
+        country: country_name,
+        flag_emoji: country_code.as_deref().and_then(flag_emoji),
+        country_code,
+    })
+}
+
+#[tauri::command]
+async fn download_geolite_db() -> Result<String, String> {
+    let app_data_dir = BaseDirs::new()
+        .map(|dirs| dirs.data_dir().join("TraceRT"))
+        .unwrap_or_else(|| std::path::PathBuf::from("./data"));
+    
+    // Create directory if it doesn't exist
+    fs::create_dir_all(&app_data_dir).await
+        .map_err(|e| format!("Failed to create directory: {}", e))?;
+    
+    let db_path = app_data_dir.join("GeoLite2-City.mmdb");
+    
+    // Check if file already exists
+    if db_path.exists() {
+        return Ok("Database already exists".to_string());
+    }
+    
+    download_with_retry(GEOLITE_DB_URL, &db_path).await?;
+    
+    Ok(format!("Database downloaded to: {}", db_path.display()))
+}
+
+const GEOLITE_DOWNLOAD_ATTEMPTS: u32 = 3;
+// Doubled after each failed attempt
+const GEOLITE_DOWNLOAD_BACKOFF: std::time::Duration = std::time::Duration::from_secs(2);
+
+// Download url to dest, retrying network errors, 429 and 5xx. The body is streamed to a
+// temporary file that only replaces dest once complete, so a failed attempt leaves nothing behind.
+async fn download_with_retry(url: &str, dest: &std::path::Path) -> Result<(), String> {
+    download_with_backoff(url, dest, GEOLITE_DOWNLOAD_BACKOFF).await
+}
+
+async fn download_with_backoff(url: &str, dest: &std::path::Path, first_backoff: std::time::Duration) -> Result<(), String> {
+    let temp_path = dest.with_extension("part");
+    let mut backoff = first_backoff;
+    let mut attempt = 1;
+
+    loop {
+        let outcome = download_once(url, &temp_path).await;
+        match outcome {
+            Ok(()) => {
+                return fs::rename(&temp_path, dest).await.map_err(|e| {
+                    let _ = std::fs::remove_file(&temp_path);
+                    format!("Failed to save database: {}", e)
+                });
+            }
+            Err((retryable, error)) => {
+                let _ = fs::remove_file(&temp_path).await;
+                if !retryable || attempt >= GEOLITE_DOWNLOAD_ATTEMPTS {
+                    return Err(error);
+                }
+                tracing::warn!(
+                    "[Rust] [GEO] Download attempt {}/{} failed: {}, retrying in {:?}",
+                    attempt, GEOLITE_DOWNLOAD_ATTEMPTS, error, backoff
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+// A single download attempt, errors carry whether they are worth retrying
+async fn download_once(url: &str, temp_path: &std::path::Path) -> Result<(), (bool, String)> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut response = reqwest::get(url)
+        .await
+        .map_err(|e| (true, format!("Failed to download database: {}", e)))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let retryable = status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error();
+        return Err((retryable, format!("Download failed with status: {}", status)));
+    }
+
+    let mut file = fs::File::create(temp_path)
+        .await
+        .map_err(|e| (false, format!("Failed to save database: {}", e)))?;
+
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| (true, format!("Failed to read response: {}", e)))?
+    {
+        file.write_all(&chunk)
+            .await
+            .map_err(|e| (false, format!("Failed to save database: {}", e)))?;
+    }
+
+    file.flush()
+        .await
+        .map_err(|e| (false, format!("Failed to save database: {}", e)))
+}
+
+#[derive(Serialize)]
+struct GeoDbInfo {
+    loaded: bool,
+    load_status: GeoDbLoadStatus,
+    path: Option<String>,
+    database_type: Option<String>,
+    build_epoch: Option<u64>,
+    build_time: Option<String>,
+}
+
+#[tauri::command]
+fn geo_db_info() -> GeoDbInfo {
+    let load_status = *GEO_DB_STATUS.read().expect("Failed to acquire geo database status lock");
+    match current_geo_db() {
+        Some(db) => {
+            let build_epoch = db.reader.metadata.build_epoch;
+            GeoDbInfo {
+                loaded: true,
+                load_status,
+                path: Some(db.path.display().to_string()),
+                database_type: Some(db.reader.metadata.database_type.clone()),
+                build_epoch: Some(build_epoch),
+                build_time: chrono::DateTime::from_timestamp(build_epoch as i64, 0).map(|t| t.to_rfc3339()),
+            }
+        }
+        None => GeoDbInfo {
+            loaded: false,
+            load_status,
+            path: None,
+            database_type: None,
+            build_epoch: None,
+            build_time: None,
+        },
+    }
+}
+
+#[derive(Serialize)]
+struct GeoDbUpdateInfo {
+    update_available: bool,
+    local_build_time: Option<String>,
+    local_age_days: Option<i64>,
+    remote_last_modified: Option<String>,
+    // Set when the remote could not be checked (offline, missing header, ...)
+    error: Option<String>,
+}
+
+#[tauri::command]
+async fn check_geo_db_update() -> Result<GeoDbUpdateInfo, String> {
+    let info = geo_db_info();
+    let local_build = info.build_epoch
+        .and_then(|epoch| chrono::DateTime::from_timestamp(epoch as i64, 0));
+    let local_age_days = local_build.map(|built| (chrono::Utc::now() - built).num_days());
+
+    let remote = fetch_geolite_last_modified().await;
+    if let Err(ref e) = remote {
+        tracing::warn!("[Rust] [GEO] Could not check for GeoLite database update: {}", e);
+    }
+
+    let update_available = match (&remote, local_build) {
+        (Ok(remote_time), Some(local_time)) => *remote_time > local_time,
+        // Nothing loaded locally, any reachable remote is an update
+        (Ok(_), None) => true,
+        (Err(_), _) => false,
+    };
+
+    Ok(GeoDbUpdateInfo {
+        update_available,
+        local_build_time: info.build_time,
+        local_age_days,
+        remote_last_modified: remote.as_ref().ok().map(|t| t.to_rfc3339()),
+        error: remote.err(),
+    })
+}
+
+async fn fetch_geolite_last_modified() -> Result<chrono::DateTime<chrono::Utc>, String> {
+    let client = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(10))
+        // The GitHub API rejects requests without a User-Agent
+        .user_agent("TraceRT")
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+
+    let response = client.head(GEOLITE_DB_URL)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to reach update server: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(format!("Update check failed with status: {}", response.status()));
+    }
+
+    if let Some(last_modified) = response.headers()
+        .get(reqwest::header::LAST_MODIFIED)
+        .and_then(|value| value.to_str().ok())
+    {
+        return parse_last_modified(last_modified);
+    }
+
+    tracing::debug!("[Rust] [GEO] No Last-Modified on the database download, asking the GitHub API");
+    let response = client.get(GEOLITE_COMMITS_URL)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to reach GitHub API: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(format!("GitHub API request failed with status: {}", response.status()));
+    }
+
+    let body = response.text()
+        .await
+        .map_err(|e| format!("Failed to read GitHub API response: {}", e))?;
+    latest_commit_date(&body)
+}
+
+fn parse_last_modified(value: &str) -> Result<chrono::DateTime<chrono::Utc>, String> {
+    chrono::DateTime::parse_from_rfc2822(value)
+        .map(|t| t.with_timezone(&chrono::Utc))
+        .map_err(|e| format!("Invalid Last-Modified header '{}': {}", value, e))
+}
+
+#[derive(Deserialize)]
+struct GithubCommit {
+    commit: GithubCommitDetail,
+}
+
+#[derive(Deserialize)]
+struct GithubCommitDetail {
+    committer: GithubCommitter,
+}
+
+#[derive(Deserialize)]
+struct GithubCommitter {
+    date: String,
+}
+
+// Committer date of the first entry of a GitHub "list commits" response
+fn latest_commit_date(body: &str) -> Result<chrono::DateTime<chrono::Utc>, String> {
+    let commits: Vec<GithubCommit> = serde_json::from_str(body)
+        .map_err(|e| format!("Invalid GitHub API response: {}", e))?;
+    let date = &commits.first()
+        .ok_or_else(|| "GitHub API returned no commits for the database".to_string())?
+        .commit.committer.date;
+
+    chrono::DateTime::parse_from_rfc3339(date)
+        .map(|t| t.with_timezone(&chrono::Utc))
+        .map_err(|e| format!("Invalid commit date '{}': {}", date, e))
+}
+
+// Hops that have map coordinates, as (hop, lat, lng)
+fn geolocated_hops(result: &TraceResult) -> Vec<(&HopData, f64, f64)> {
+    result.hops.iter()
+        .filter_map(|hop| {
+            let geo = hop.geo.as_ref()?;
+            Some((hop, geo.lat?, geo.lng?))
+        })
+        .collect()
+}
+
+const EARTH_RADIUS_KM: f64 = 6371.0;
+// Below this the first and last located hops are effectively the same place and a ratio means nothing
+const MIN_DIRECT_DISTANCE_KM: f64 = 1.0;
+
+// Great-circle distance between two points given in degrees
+fn haversine_km(lat1: f64, lng1: f64, lat2: f64, lng2: f64) -> f64 {
+    let (phi1, phi2) = (lat1.to_radians(), lat2.to_radians());
+    let d_phi = (lat2 - lat1).to_radians();
+    let d_lambda = (lng2 - lng1).to_radians();
+    let a = (d_phi / 2.0).sin().powi(2) + phi1.cos() * phi2.cos() * (d_lambda / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().min(1.0).asin()
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct RoutingEfficiency {
+    // Sum of the great-circle distances between consecutive located hops
+    #[serde(rename = "pathKm")]
+    path_km: f64,
+    // Great-circle distance from the first to the last located hop
+    #[serde(rename = "directKm")]
+    direct_km: f64,
+    // path_km / direct_km, 1.0 is a straight line and large values point at a detour
+    // (e.g. traffic hairpinning through another continent)
+    efficiency: f64,
+    #[serde(rename = "locatedHops")]
+    located_hops: usize,
+}
+
+// Hops without coordinates are skipped, the path is measured between the ones that have them.
+// None when fewer than two hops are located or they are all in one place.
+fn routing_efficiency(result: &TraceResult) -> Option<RoutingEfficiency> {
+    let located = geolocated_hops(result);
+    let (_, first_lat, first_lng) = *located.first()?;
+    let (_, last_lat, last_lng) = *located.last()?;
+
+    let direct_km = haversine_km(first_lat, first_lng, last_lat, last_lng);
+    if direct_km < MIN_DIRECT_DISTANCE_KM {
+        return None;
+    }
+    let path_km: f64 = located.windows(2)
+        .map(|pair| haversine_km(pair[0].1, pair[0].2, pair[1].1, pair[1].2))
+        .sum();
+
+    Some(RoutingEfficiency {
+        path_km,
+        direct_km,
+        efficiency: path_km / direct_km,
+        located_hops: located.len(),
+    })
+}
+
+// GeoJSON FeatureCollection with the path as a LineString and one Point per geolocated hop
+fn trace_to_geojson(result: &TraceResult) -> serde_json::Value {
+    let located = geolocated_hops(result);
+    let mut features = Vec::new();
+
+    if located.len() >= 2 {
+        // GeoJSON positions are [longitude, latitude]
+        let coordinates: Vec<[f64; 2]> = located.iter().map(|(_, lat, lng)| [*lng, *lat]).collect();
+        features.push(serde_json::json!({
+            "type": "Feature",
+            "geometry": { "type": "LineString", "coordinates": coordinates },
+            "properties": { "target": result.target, "resolvedIp": result.resolved_ip },
+        }));
+    }
+
+    for (hop, lat, lng) in &located {
+        let geo = hop.geo.as_ref();
+        features.push(serde_json::json!({
+            "type": "Feature",
+            "geometry": { "type": "Point", "coordinates": [lng, lat] },
+            "properties": {
+                "hop": hop.hop,
+                "ip": hop.ip,
+                "host": hop.host,
+                "city": geo.and_then(|g| g.city.clone()),
+                "country": geo.and_then(|g| g.country.clone()),
+                "latency": hop.avg_latency,
+            },
+        }));
+    }
+
+    serde_json::json!({
+        "type": "FeatureCollection",
+        "features": features,
+    })
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+// KML document with a Placemark per geolocated hop and the path as a LineString
+fn trace_to_kml(result: &TraceResult) -> String {
+    let located = geolocated_hops(result);
+    let mut kml = String::new();
+
+    kml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    kml.push_str("<kml xmlns=\"http://www.opengis.net/kml/2.2\">\n<Document>\n");
+    kml.push_str(&format!("<name>Trace to {}</name>\n", xml_escape(&result.target)));
+
+    for (hop, lat, lng) in &located {
+        let geo = hop.geo.as_ref();
+        let label = hop.ip.as_deref().unwrap_or("*");
+        let place = [geo.and_then(|g| g.city.as_deref()), geo.and_then(|g| g.country.as_deref())]
+            .iter()
+            .flatten()
+            .copied()
+            .collect::<Vec<&str>>()
+            .join(", ");
+        let latency = hop.avg_latency.map(|ms| format!("{} ms", ms)).unwrap_or_else(|| "*".to_string());
+
+        kml.push_str("<Placemark>\n");
+        kml.push_str(&format!("<name>{}. {}</name>\n", hop.hop, xml_escape(label)));
+        let description = format!("{} {}", place, latency);
+        kml.push_str(&format!("<description>{}</description>\n", xml_escape(description.trim())));
+        kml.push_str(&format!("<Point><coordinates>{},{}</coordinates></Point>\n", lng, lat));
+        kml.push_str("</Placemark>\n");
+    }
+
+    if located.len() >= 2 {
+        let coordinates: Vec<String> = located.iter().map(|(_, lat, lng)| format!("{},{}", lng, lat)).collect();
+        kml.push_str("<Placemark>\n<name>Path</name>\n<LineString><tessellate>1</tessellate><coordinates>");
+        kml.push_str(&coordinates.join(" "));
+        kml.push_str("</coordinates></LineString>\n</Placemark>\n");
+    }
+
+    kml.push_str("</Document>\n</kml>\n");
+    kml
+}
+
+#[tauri::command]
+async fn export_trace_geojson(result: TraceResult, path: String) -> Result<(), String> {
+    let geojson = serde_json::to_string_pretty(&trace_to_geojson(&result))
+        .map_err(|e| format!("Failed to serialize GeoJSON: {}", e))?;
+    fs::write(&path, geojson).await
+        .map_err(|e| format!("Failed to write {}: {}", path, e))
+}
+
+#[tauri::command]
+async fn export_trace_kml(result: TraceResult, path: String) -> Result<(), String> {
+    fs::write(&path, trace_to_kml(&result)).await
+        .map_err(|e| format!("Failed to write {}: {}", path, e))
+}
+
+#[tauri::command]
+fn estimate_routing_efficiency(result: TraceResult) -> Option<RoutingEfficiency> {
+    routing_efficiency(&result)
+}
+
+// Smaller form of a TraceResult for history storage and transfer. The raw transcript and the
+// per-probe details are dropped, hops keep what the hop table and map need, and empty fields
+// are left out of the JSON.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CompactTrace {
+    target: String,
+    #[serde(rename = "resolvedIp", skip_serializing_if = "Option::is_none")]
+    resolved_ip: Option<String>,
+    #[serde(rename = "startTime")]
+    start_time: String,
+    #[serde(rename = "endTime", skip_serializing_if = "Option::is_none")]
+    end_time: Option<String>,
+    #[serde(rename = "reachedDestination")]
+    reached_destination: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    hops: Vec<CompactHop>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CompactHop {
+    hop: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    host: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ip: Option<String>,
+    latencies: Vec<Option<f64>>,
+    #[serde(rename = "avgLatency", skip_serializing_if = "Option::is_none")]
+    avg_latency: Option<f64>,
+    status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    geo: Option<CompactGeo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CompactGeo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lat: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lng: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    city: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    country: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    country_code: Option<String>,
+}
+
+impl TraceResult {
+    fn to_compact(&self) -> CompactTrace {
+        CompactTrace {
+            target: self.target.clone(),
+            resolved_ip: self.resolved_ip.clone(),
+            start_time: self.start_time.clone(),
+            end_time: self.end_time.clone(),
+            reached_destination: self.reached_destination,
+            error: self.error.clone(),
+            hops: self.hops.iter().map(|hop| CompactHop {
+                hop: hop.hop,
+                host: hop.host.clone(),
+                ip: hop.ip.clone(),
+                latencies: hop.latencies.clone(),
+                avg_latency: hop.avg_latency,
+                status: hop.status.clone(),
+                geo: hop.geo.as_ref().map(|geo| CompactGeo {
+                    lat: geo.lat,
+                    lng: geo.lng,
+                    city: geo.city.clone(),
+                    country: geo.country.clone(),
+                    country_code: geo.country_code.clone(),
+                }),
+            }).collect(),
+        }
+    }
+}
+
+impl CompactTrace {
+    // Back to a full TraceResult for the hop table, the dropped fields come back empty
+    fn into_result(self) -> TraceResult {
+        TraceResult {
+            target: self.target,
+            resolved_ip: self.resolved_ip,
+            hops: self.hops.into_iter().map(|hop| HopData {
+                hop: hop.hop,
+                host: hop.host,
+                ip: hop.ip,
+                latencies: hop.latencies,
+                avg_latency: hop.avg_latency,
+                status: hop.status,
+                geo: hop.geo.map(|geo| GeoLocation {
+                    lat: geo.lat,
+                    lng: geo.lng,
+                    city: geo.city,
+                    country: geo.country,
+                    flag_emoji: geo.country_code.as_deref().and_then(flag_emoji),
+                    country_code: geo.country_code,
+                }),
+                annotation: None,
+                received_at: String::new(),
+                well_known: None,
+                probe_ips: Vec::new(),
+            }).collect(),
+            raw_output: String::new(),
+            stdout_raw: String::new(),
+            stderr_raw: String::new(),
+            start_time: self.start_time,
+            end_time: self.end_time,
+            reached_destination: self.reached_destination,
+            error: self.error,
+        }
+    }
+}
+
+#[tauri::command]
+async fn export_trace_compact_json(result: TraceResult, path: String) -> Result<(), String> {
+    let json = serde_json::to_string(&result.to_compact())
+        .map_err(|e| format!("Failed to serialize trace: {}", e))?;
+    fs::write(&path, json).await
+        .map_err(|e| format!("Failed to write {}: {}", path, e))
+}
+
+#[tauri::command]
+async fn import_trace_compact_json(path: String) -> Result<TraceResult, String> {
+    let json = fs::read_to_string(&path).await
+        .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let compact: CompactTrace = serde_json::from_str(&json)
+        .map_err(|e| format!("Invalid compact trace {}: {}", path, e))?;
+    Ok(compact.into_result())
+}
+
+// Hops as a graph for load-balanced (ECMP) paths. Each node is one router at one hop, its children
+// are the routers the same probe reached at the next hop that answered it.
+#[derive(Serialize)]
+struct PathTree {
+    root: String,
+    nodes: Vec<PathNode>,
+}
+
+#[derive(Serialize)]
+struct PathNode {
+    // "<hop>:<ip>", or "source" for the root
+    id: String,
+    hop: u32,
+    ip: Option<String>,
+    host: Option<String>,
+    // Probes that passed through this router
+    probes: usize,
+    children: Vec<String>,
+}
+
+fn trace_to_path_tree(result: &TraceResult) -> PathTree {
+    let root = "source".to_string();
+    let mut nodes = vec![PathNode {
+        id: root.clone(),
+        hop: 0,
+        ip: None,
+        host: None,
+        probes: 0,
+        children: Vec::new(),
+    }];
+    let mut node_index: HashMap<String, usize> = HashMap::new();
+
+    // Probe n of each hop is followed as one flow, remembering the last router it reached
+    let flows = result.hops.iter().map(|hop| hop.latencies.len()).max().unwrap_or(0);
+    let mut last_node = vec![0usize; flows];
+
+    for hop in &result.hops {
+        for (flow, last) in last_node.iter_mut().enumerate() {
+            let answered = hop.latencies.get(flow).copied().flatten().is_some();
+            // Results saved before per-probe addresses were recorded only have the hop address
+            let ip = match hop.probe_ips.get(flow).cloned().flatten() {
+                Some(ip) => ip,
+                None if hop.probe_ips.is_empty() && answered => match hop.ip.clone() {
+                    Some(ip) => ip,
+                    None => continue,
+                },
+                None => continue,
+            };
+
+            let id = format!("{}:{}", hop.hop, ip);
+            let node = *node_index.entry(id.clone()).or_insert_with(|| {
+                nodes.push(PathNode {
+                    id: id.clone(),
+                    hop: hop.hop,
+                    host: if hop.ip.as_deref() == Some(ip.as_str()) { hop.host.clone() } else { None },
+                    ip: Some(ip),
+                    probes: 0,
+                    children: Vec::new(),
+                });
+                nodes.len() - 1
+            });
+            nodes[node].probes += 1;
+
+            if !nodes[*last].children.contains(&id) {
+                nodes[*last].children.push(id);
+            }
+            *last = node;
+        }
+    }
+
+    PathTree { root, nodes }
+}
+
+#[tauri::command]
+fn build_path_tree(result: TraceResult) -> PathTree {
+    trace_to_path_tree(&result)
+}
+
+// Send a parsed hop to the UI, or print it as a JSON line when running headless
+async fn emit_hop(app: Option<&AppHandle>, trace_id: &str, hop_data: HopData) -> Result<(), String> {
+    match app {
+        Some(app) => emit_hop_update(app.clone(), trace_id, hop_data).await,
+        None => {
+            println!("{}", hop_json_line(&hop_data)?);
+            Ok(())
+        }
+    }
+}
+
+// One line of the headless output, serde_json never puts a raw newline inside a string
+fn hop_json_line(hop_data: &HopData) -> Result<String, String> {
+    serde_json::to_string(hop_data)
+        .map_err(|e| format!("Failed to serialize hop: {}", e))
+}
+
+// Final update for a hop once all of its probes are in, only sent with settledEvents
+fn emit_hop_settled(app: Option<&AppHandle>, trace_id: &str, hop_data: HopData) {
+    let app = match app {
+        Some(app) => app,
+        None => return,
+    };
+
+    let event_payload = serde_json::json!({
+        "trace_id": trace_id,
+        "hop_data": hop_data
+    });
+
+    if let Err(e) = app.emit("hop:settled", &event_payload) {
+        tracing::warn!("[Rust] [TRACE] Failed to emit 'hop:settled' event: {}", e);
+    }
+}
+
+// hop:geo payload for a hop enrichment has placed on the map. None without coordinates, which
+// includes private/internal hops that only carry a location label.
+fn hop_geo_payload(trace_id: &str, hop_data: &HopData) -> Option<serde_json::Value> {
+    let geo = hop_data.geo.as_ref().filter(|geo| geo.lat.is_some() && geo.lng.is_some())?;
+    Some(serde_json::json!({
+        "trace_id": trace_id,
+        "hop": hop_data.hop,
+        "geo": geo
+    }))
+}
+
+// Sent after the hop:update of an enriched hop so the map can move just that marker
+fn emit_hop_geo(app: Option<&AppHandle>, trace_id: &str, hop_data: &HopData) {
+    let app = match app {
+        Some(app) => app,
+        None => return,
+    };
+    let event_payload = match hop_geo_payload(trace_id, hop_data) {
+        Some(payload) => payload,
+        None => return,
+    };
+
+    if let Err(e) = app.emit("hop:geo", &event_payload) {
+        tracing::warn!("[Rust] [TRACE] Failed to emit 'hop:geo' event: {}", e);
+    }
+}
+
+// Add a new event for individual hop updates
+#[tauri::command]
+async fn emit_hop_update(
+    app: tauri::AppHandle,
+    trace_id: &str,
+    hop_data: HopData
+) -> Result<(), String> {
+    tracing::debug!("[Rust] [TRACE] emit_hop_update called with trace_id: {}, hop: {}", trace_id, hop_data.hop);
+    
+    let event_payload = serde_json::json!({
+        "trace_id": trace_id,
+        "hop_data": hop_data
+    });
+    
+    let result = app.emit("hop:update", &event_payload)
+        .map_err(|e| format!("Failed to emit hop:update event: {}", e));
+    
+    tracing::debug!("[Rust] [TRACE] emit 'hop:update' event -> {:?}", result);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_nslookup_ptr_output() {
+        let unix = "Server:\t\t127.0.0.53\nAddress:\t127.0.0.53#53\n\nNon-authoritative answer:\n8.8.8.8.in-addr.arpa\tname = dns.google.\n";
+        assert_eq!(parse_nslookup_ptr(unix).as_deref(), Some("dns.google"));
+
+        let windows = "Server:  router.lan\nAddress:  192.168.1.1\n\nName:    dns.google\nAddress:  8.8.8.8\n";
+        assert_eq!(parse_nslookup_ptr(windows).as_deref(), Some("dns.google"));
+
+        let nxdomain = "** server can't find 1.2.0.192.in-addr.arpa: NXDOMAIN\n";
+        assert_eq!(parse_nslookup_ptr(nxdomain), None);
+    }
+
+    #[tokio::test]
+    async fn dns_cache_answers_before_querying() {
+        let named: std::net::IpAddr = "192.0.2.71".parse().unwrap();
+        let unresolvable: std::net::IpAddr = "192.0.2.72".parse().unwrap();
+        assert_eq!(dns_cache_get(&named), None);
+
+        dns_cache_insert(named, Some("cached.example".to_string()));
+        dns_cache_insert(unresolvable, None);
+
+        assert_eq!(dns_cache_get(&named), Some(Some("cached.example".to_string())));
+        // Negative results are hits too
+        assert_eq!(dns_cache_get(&unresolvable), Some(None));
+        assert_eq!(reverse_dns_lookup(named).await.as_deref(), Some("cached.example"));
+        assert_eq!(reverse_dns_lookup(unresolvable).await, None);
+    }
+
+    #[test]
+    fn reads_remote_database_dates() {
+        let last_modified = parse_last_modified("Tue, 13 Oct 2026 06:12:41 GMT").unwrap();
+        assert_eq!(last_modified.to_rfc3339(), "2026-10-13T06:12:41+00:00");
+        assert!(parse_last_modified("yesterday").is_err());
+
+        let commits = r#"[{"sha":"4b6a1f2","commit":{"author":{"date":"2026-10-12T22:00:00Z"},"committer":{"name":"GitHub","date":"2026-10-13T06:12:41Z"}}}]"#;
+        assert_eq!(latest_commit_date(commits).unwrap().to_rfc3339(), "2026-10-13T06:12:41+00:00");
+        assert!(latest_commit_date("[]").is_err());
+        assert!(latest_commit_date(r#"{"message":"API rate limit exceeded"}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn headless_output_is_one_hop_per_json_line() {
+        let transcript = "traceroute to dns.google (8.8.8.8), 30 hops max, 60 byte packets\n 1  _gateway (192.168.1.1)  0.345 ms  0.234 ms  0.198 ms\n 2  * * *\n 3  dns.google (8.8.8.8)  10.5 ms  11.2 ms  9.8 ms\n";
+        let result = parse_trace_text(transcript.to_string(), "unix".to_string()).await.unwrap();
+
+        let output: String = result.hops.iter()
+            .map(|hop| hop_json_line(hop).unwrap() + "\n")
+            .collect();
+        let parsed: Vec<HopData> = output.lines()
+            .map(|line| serde_json::from_str(line).expect("every line is a complete JSON hop"))
+            .collect();
+
+        assert_eq!(parsed.len(), 3);
+        assert_eq!(parsed.iter().map(|hop| hop.hop).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(parsed[0].ip.as_deref(), Some("192.168.1.1"));
+        assert_eq!(parsed[1].status, "timeout");
+        assert_eq!(parsed[2].latencies, vec![Some(10.5), Some(11.2), Some(9.8)]);
+    }
+
+    fn family_options(family: IpFamily) -> TraceOptions {
+        TraceOptions { family: Some(family), ..Default::default() }
+    }
+
+    #[test]
+    fn literal_address_must_match_family() {
+        assert!(prepare_trace_command("2001:4860:4860::8888", &family_options(IpFamily::V4)).is_err());
+        assert!(prepare_trace_command("8.8.8.8", &family_options(IpFamily::V6)).is_err());
+        assert!(prepare_trace_command("8.8.8.8", &family_options(IpFamily::V4)).is_ok());
+        assert!(prepare_trace_command("dns.google", &family_options(IpFamily::V6)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn auto_family_of_a_literal_is_its_own() {
+        assert_eq!(resolve_auto_family("8.8.8.8").await, IpFamily::V4);
+        assert_eq!(resolve_auto_family("2001:4860:4860::8888").await, IpFamily::V6);
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    #[test]
+    fn family_maps_to_traceroute_flags() {
+        let (cmd, args) = prepare_trace_command("dns.google", &family_options(IpFamily::V4)).unwrap();
+        assert_eq!(cmd, "traceroute");
+        assert!(args.contains(&"-4".to_string()));
+
+        let (cmd, args) = prepare_trace_command("dns.google", &family_options(IpFamily::V6)).unwrap();
+        assert_eq!(cmd, "traceroute");
+        assert!(args.contains(&"-6".to_string()));
+
+        let (_, args) = prepare_trace_command("dns.google", &TraceOptions::default()).unwrap();
+        assert!(!args.iter().any(|arg| arg == "-4" || arg == "-6"));
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn family_maps_to_traceroute6_on_macos() {
+        let (cmd, args) = prepare_trace_command("dns.google", &family_options(IpFamily::V6)).unwrap();
+        assert_eq!(cmd, "traceroute6");
+        assert!(!args.contains(&"-6".to_string()));
+
+        let (cmd, args) = prepare_trace_command("dns.google", &family_options(IpFamily::V4)).unwrap();
+        assert_eq!(cmd, "traceroute");
+        assert!(!args.contains(&"-4".to_string()));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn family_maps_to_tracert_flags() {
+        let (cmd, args) = prepare_trace_command("dns.google", &family_options(IpFamily::V4)).unwrap();
+        assert_eq!(cmd, "tracert");
+        assert!(args.contains(&"-4".to_string()));
+
+        let (_, args) = prepare_trace_command("dns.google", &family_options(IpFamily::V6)).unwrap();
+        assert!(args.contains(&"-6".to_string()));
+    }
+
+    #[test]
+    fn hop_limit_is_always_passed_to_the_tool() {
+        let flag = if cfg!(windows) { "-h" } else { "-m" };
+        let limit = |options: &TraceOptions| {
+            let (_, args) = prepare_trace_command("dns.google", options).unwrap();
+            let at = args.iter().position(|arg| arg == flag).expect("hop limit flag");
+            args[at + 1].clone()
+        };
+        assert_eq!(limit(&TraceOptions::default()), DEFAULT_MAX_HOPS.to_string());
+        assert_eq!(limit(&TraceOptions { max_hops: Some(12), ..Default::default() }), "12");
+    }
+
+    #[test]
+    fn hops_never_reached_are_marked_skipped() {
+        let seen: Vec<HopData> = [1, 2, 4]
+            .iter()
+            .map(|hop| HopData { status: "success".to_string(), ..placeholder_hop(*hop, "pending") })
+            .collect();
+
+        let unreached = unreached_hops(6, &seen);
+        assert_eq!(unreached.iter().map(|hop| hop.hop).collect::<Vec<_>>(), vec![3, 5, 6]);
+        assert!(unreached.iter().all(|hop| hop.status == "skipped"));
+        assert!(unreached_hops(2, &seen).is_empty());
+    }
+
+    #[test]
+    fn tracert_unreachable_lines_fail_the_trace() {
+        let host = parse_traceroute_line_as("  3  10.0.0.1  reports: Destination host unreachable.", Platform::Windows).unwrap();
+        assert_eq!(host.hop, 3);
+        assert_eq!(host.status, "unreachable");
+        assert_eq!(host.ip.as_deref(), Some("10.0.0.1"));
+        assert_eq!(host.annotation.as_deref(), Some("Destination host unreachable (reported by 10.0.0.1)"));
+
+        let net = parse_traceroute_line_as("  4     *        *        *     Destination net unreachable.", Platform::Windows).unwrap();
+        assert_eq!(net.hop, 4);
+        assert_eq!(net.status, "unreachable");
+        assert_eq!(net.annotation.as_deref(), Some("Destination net unreachable"));
+
+        let first = parse_traceroute_line_as("  1    <1 ms    <1 ms    <1 ms  192.168.1.1", Platform::Windows).unwrap();
+        let (error, reached_destination) = path_outcome(&[first.clone(), host], Some("10.0.0.1"));
+        assert_eq!(error.as_deref(), Some("Hop 3: Destination host unreachable (reported by 10.0.0.1)"));
+        assert!(!reached_destination);
+
+        let (error, reached_destination) = path_outcome(&[first], Some("192.168.1.1"));
+        assert!(error.is_none());
+        assert!(reached_destination);
+    }
+
+    async fn read_all_lines(input: &[u8]) -> Vec<String> {
+        let mut reader = ChunkedLines::new(input);
+        let mut lines = Vec::new();
+        while let Some(line) = reader.next_line().await.unwrap() {
+            lines.push(line.into_owned());
+        }
+        lines
+    }
+
+    #[tokio::test]
+    async fn chunked_lines_split_like_lines() {
+        assert_eq!(read_all_lines(b"one\ntwo\r\n\nlast").await, vec!["one", "two", "", "last"]);
+        assert_eq!(read_all_lines(b"trailing\n").await, vec!["trailing"]);
+        assert!(read_all_lines(b"").await.is_empty());
+
+        // Lines straddling the chunk boundaries come out whole
+        let input: String = (0..2000).map(|n| format!(" {}  10.0.{}.1  {}.5 ms\n", n, n % 256, n)).collect();
+        assert!(input.len() > 3 * ChunkedLines::<&[u8]>::CHUNK_SIZE);
+        assert_eq!(read_all_lines(input.as_bytes()).await, input.lines().collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn chunked_lines_borrow_valid_lines_and_replace_invalid_utf8() {
+        let mut reader = ChunkedLines::new(&b"  1  gw (192.168.1.1)  0.5 ms\n  2  \xfe\xff host\n"[..]);
+        let first = reader.next_line().await.unwrap().unwrap();
+        assert!(matches!(first, std::borrow::Cow::Borrowed(_)));
+        assert_eq!(first, "  1  gw (192.168.1.1)  0.5 ms");
+
+        let second = reader.next_line().await.unwrap().unwrap();
+        assert_eq!(second, "  2  \u{fffd}\u{fffd} host");
+        assert!(reader.next_line().await.unwrap().is_none());
+    }
+
+    fn hop_with_latencies(latencies: &[Option<f64>]) -> HopData {
+        HopData {
+            latencies: latencies.to_vec(),
+            ..placeholder_hop(1, "success")
+        }
+    }
+
+    #[test]
+    fn jitter_is_mean_difference_of_consecutive_rtts() {
+        let mut stats = HopStats::default();
+        assert_eq!(stats.jitter_ms(), None);
+
+        stats.record(&hop_with_latencies(&[Some(10.0), Some(12.0)]), DEFAULT_STATS_WINDOW);
+        assert_eq!(stats.jitter_ms(), Some(2.0));
+
+        // |12-11| + |11-15| + |15-15| over 4 differences, lost probes add no sample
+        stats.record(&hop_with_latencies(&[Some(11.0), None, Some(15.0), Some(15.0)]), DEFAULT_STATS_WINDOW);
+        assert_eq!(stats.jitter_ms(), Some(7.0 / 4.0));
+        assert_eq!(stats.sent, 6);
+        assert_eq!(stats.received, 5);
+
+        // Only the window's samples count: 15, 15
+        let mut windowed = HopStats::default();
+        windowed.record(&hop_with_latencies(&[Some(10.0), Some(30.0), Some(15.0), Some(15.0)]), 2);
+        assert_eq!(windowed.jitter_ms(), Some(0.0));
+    }
+
+    fn unix_hop(line: &str) -> HopData {
+        parse_traceroute_line_as(line, Platform::Unix).expect("hop line")
+    }
+
+    #[test]
+    fn parses_gnu_and_busybox_hop_lines() {
+        // GNU traceroute with a resolved name
+        let gnu = unix_hop(" 1  _gateway (192.168.1.1)  0.345 ms  0.234 ms  0.198 ms");
+        assert_eq!(gnu.host.as_deref(), Some("_gateway"));
+        assert_eq!(gnu.ip.as_deref(), Some("192.168.1.1"));
+        assert_eq!(gnu.latencies, vec![Some(0.345), Some(0.234), Some(0.198)]);
+        assert_eq!(gnu.status, "success");
+
+        // BusyBox repeats the address in parentheses, it is not a hostname
+        let busybox = unix_hop(" 1  192.168.1.1 (192.168.1.1)  0.345 ms  0.234 ms  0.198 ms");
+        assert_eq!(busybox.host, None);
+        assert_eq!(busybox.ip.as_deref(), Some("192.168.1.1"));
+        assert_eq!(busybox.latencies.len(), 3);
+
+        // BusyBox builds without the copy, and -n
+        let bare = unix_hop(" 1  192.168.1.1  0.345 ms  0.234 ms  0.198 ms");
+        assert_eq!(bare.host, None);
+        assert_eq!(bare.ip.as_deref(), Some("192.168.1.1"));
+        assert_eq!(bare.latencies.len(), 3);
+
+        // Glued unit
+        let glued = unix_hop(" 3  10.0.0.3  1.5ms  1.6ms  1.7ms");
+        assert_eq!(glued.latencies, vec![Some(1.5), Some(1.6), Some(1.7)]);
+
+        // BusyBox pads timeouts with two spaces
+        let silent = unix_hop(" 2  *  *  *");
+        assert_eq!(silent.ip, None);
+        assert_eq!(silent.latencies, vec![None, None, None]);
+        assert_eq!(silent.status, "timeout");
+
+        let mixed = unix_hop(" 5  10.0.0.1 (10.0.0.1)  12.345 ms *  12.1 ms");
+        assert_eq!(mixed.latencies, vec![Some(12.345), None, Some(12.1)]);
+        assert_eq!(mixed.status, "success");
+    }
+
+    #[test]
+    fn parses_probes_answered_by_different_routers() {
+        let hop = unix_hop(" 7  a.example (10.0.0.1)  1.2 ms b.example (10.0.0.2)  1.5 ms  1.4 ms");
+        assert_eq!(hop.host.as_deref(), Some("a.example"));
+        assert_eq!(hop.ip.as_deref(), Some("10.0.0.1"));
+        assert_eq!(hop.latencies, vec![Some(1.2), Some(1.5), Some(1.4)]);
+        assert_eq!(
+            hop.probe_ips,
+            vec![Some("10.0.0.1".to_string()), Some("10.0.0.2".to_string()), Some("10.0.0.2".to_string())]
+        );
+
+        let bare = unix_hop(" 7  10.0.0.1  1.2 ms 10.0.0.2  1.5 ms *");
+        assert_eq!(
+            bare.probe_ips,
+            vec![Some("10.0.0.1".to_string()), Some("10.0.0.2".to_string()), None]
+        );
+    }
+
+    #[test]
+    fn parses_unreachable_flags() {
+        let hop = unix_hop(" 6  10.0.0.1 (10.0.0.1)  3.1 ms !H  3.2 ms !H  3.0 ms !H");
+        assert_eq!(hop.ip.as_deref(), Some("10.0.0.1"));
+        assert_eq!(hop.latencies, vec![Some(3.1), Some(3.2), Some(3.0)]);
+        assert_eq!(hop.status, "unreachable");
+        assert_eq!(hop.annotation.as_deref(), Some("Host unreachable (!H)"));
+
+        let coded = unix_hop(" 6  10.0.0.1  3.1 ms !<10>  3.2 ms !10");
+        assert_eq!(coded.status, "unreachable");
+        assert_eq!(unix_unreachable_flag("!10"), "ICMP unreachable code 10 (!10)");
+    }
+
+    #[test]
+    fn detects_busybox_usage_text() {
+        let busybox = "BusyBox v1.36.1 (2023-07-27 17:12:24 UTC) multi-call binary.\n\nUsage: traceroute [-46FIlnrv] [-f 1ST_TTL] [-m MAXTTL] [-q PROBES] [-p PORT]\n";
+        assert!(is_busybox_usage(busybox));
+
+        let gnu = "Usage:\n  traceroute [ -46dFITnreAUDV ] [ -f first_ttl ] [ -g gate,... ] [ -i device ] [ -m max_ttl ] [ -N squeries ] [ -p port ] [ -t tos ] [ -l flow_label ] [ -w MAX,HERE,NEAR ] [ -q nqueries ] [ -s src_addr ] [ -z sendwait ] [ --fwmark=num ] host [ packetlen ]\n";
+        assert!(!is_busybox_usage(gnu));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn busybox_check_runs_the_tool() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let script = std::env::temp_dir().join(format!("busybox-traceroute-{}", std::process::id()));
+        std::fs::write(&script, "#!/bin/sh\necho 'BusyBox v1.36.1 (2023-07-27 17:12:24 UTC) multi-call binary.' >&2\nexit 1\n").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let detected = is_busybox_tool(script.to_str().unwrap()).await;
+        std::fs::remove_file(&script).unwrap();
+        assert!(detected);
+
+        assert!(!is_busybox_tool("true").await);
+        assert!(!is_busybox_tool("/nonexistent/traceroute").await);
+    }
+
+    #[test]
+    fn unparseable_output_is_reported() {
+        let output = "mtr: unexpected format\n\nline 2\nline 3\nline 4\nline 5\nline 6\n";
+        let hops: Vec<HopData> = output.lines().filter_map(|line| parse_traceroute_line_as(line, Platform::Unix)).collect();
+        assert!(hops.is_empty());
+
+        let sample = parse_failed_sample(&hops, output).expect("parse_failed warning");
+        assert_eq!(sample.len(), PARSE_FAILED_SAMPLE_LINES);
+        assert_eq!(sample[0], "mtr: unexpected format");
+        assert_eq!(sample[1], "line 2");
+
+        // No output at all, or hops parsed, is not a parse failure
+        assert_eq!(parse_failed_sample(&[], " \n\n"), None);
+        assert_eq!(parse_failed_sample(&[placeholder_hop(1, "timeout")], output), None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn child_count_returns_to_zero_between_runs() {
+        let active_children = Arc::new(AtomicUsize::new(0));
+
+        for iteration in 0..3 {
+            let run = tokio::spawn(execute_trace_with_cancel(
+                None,
+                "sh".to_string(),
+                vec!["-c".to_string(), "echo ' 1  10.0.0.1  1.0 ms'; sleep 0.2".to_string()],
+                TraceOptions { resolve_dns: Some(false), ..Default::default() },
+                Arc::new(Notify::new()),
+                format!("child-count-{}", iteration),
+                active_children.clone(),
+            ));
+
+            // Counted while the tool runs...
+            let started = std::time::Instant::now();
+            while active_children.load(Ordering::SeqCst) == 0 {
+                assert!(started.elapsed() < std::time::Duration::from_secs(5), "child never counted");
+                tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+            }
+            assert_eq!(active_children.load(Ordering::SeqCst), 1);
+
+            // ...and released once it has been reaped
+            let result = run.await.unwrap().unwrap();
+            assert_eq!(result.hops.len(), 1);
+            assert_eq!(active_children.load(Ordering::SeqCst), 0);
+        }
+    }
+
+    fn trace_result(hops: Vec<HopData>) -> TraceResult {
+        TraceResult {
+            target: "dns.google".to_string(),
+            resolved_ip: Some("8.8.8.8".to_string()),
+            hops,
+            raw_output: String::new(),
+            stdout_raw: String::new(),
+            stderr_raw: String::new(),
+            start_time: "2024-01-01T00:00:00+00:00".to_string(),
+            end_time: None,
+            reached_destination: true,
+            error: None,
+        }
+    }
+
+    fn located_hop(hop: u32, ip: &str, lat: f64, lng: f64, city: &str) -> HopData {
+        HopData {
+            ip: Some(ip.to_string()),
+            latencies: vec![Some(10.0), Some(12.0)],
+            avg_latency: Some(11.0),
+            geo: Some(GeoLocation {
+                lat: Some(lat),
+                lng: Some(lng),
+                city: Some(city.to_string()),
+                country: None,
+                country_code: None,
+                flag_emoji: None,
+            }),
+            ..placeholder_hop(hop, "success")
+        }
+    }
+
+    #[test]
+    fn geojson_export_round_trips() {
+        let result = trace_result(vec![
+            located_hop(1, "203.0.113.1", 51.5, -0.12, "London"),
+            placeholder_hop(2, "timeout"),
+            located_hop(3, "8.8.8.8", 37.4, -122.1, "Mountain View"),
+        ]);
+        let geojson = trace_to_geojson(&result);
+
+        let text = serde_json::to_string(&geojson).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(parsed, geojson);
+
+        assert_eq!(parsed["type"], "FeatureCollection");
+        let features = parsed["features"].as_array().unwrap();
+        // The path plus one point per located hop, hop 2 has no coordinates
+        assert_eq!(features.len(), 3);
+        assert_eq!(features[0]["geometry"]["type"], "LineString");
+        assert_eq!(features[0]["geometry"]["coordinates"], serde_json::json!([[-0.12, 51.5], [-122.1, 37.4]]));
+        assert_eq!(features[1]["geometry"]["type"], "Point");
+        assert_eq!(features[1]["geometry"]["coordinates"], serde_json::json!([-0.12, 51.5]));
+        assert_eq!(features[1]["properties"]["hop"], 1);
+        assert_eq!(features[1]["properties"]["city"], "London");
+        assert_eq!(features[2]["properties"]["ip"], "8.8.8.8");
+        assert_eq!(features[2]["properties"]["latency"], 11.0);
+    }
+
+    // Local HTTP server answering each connection with the next (status, body), the last one repeats.
+    // Returns its address and the number of requests served.
+    async fn mock_http_server(responses: Vec<(u16, &'static str)>) -> (std::net::SocketAddr, Arc<AtomicUsize>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let hits = Arc::new(AtomicUsize::new(0));
+        let served = hits.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let index = served.fetch_add(1, Ordering::SeqCst).min(responses.len() - 1);
+                let (status, body) = responses[index];
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                    match socket.read(&mut buf).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let response = format!(
+                    "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        (addr, hits)
+    }
+
+    #[tokio::test]
+    async fn online_geo_fallback_fills_and_caches() {
+        let (addr, hits) = mock_http_server(vec![
+            (503, ""),
+            (200, r#"{"success":true,"latitude":52.37,"longitude":4.89,"city":"Amsterdam","country":"Netherlands","country_code":"NL"}"#),
+        ])
+        .await;
+        let url_template = format!("http://{}/{{ip}}", addr);
+
+        // A failed request keeps the unknown location and isn't cached
+        assert!(geo_lookup_online("198.51.100.7", &url_template).await.is_none());
+
+        let geo = geo_lookup_online("198.51.100.7", &url_template).await.expect("fallback location");
+        assert_eq!(geo.lat, Some(52.37));
+        assert_eq!(geo.lng, Some(4.89));
+        assert_eq!(geo.city.as_deref(), Some("Amsterdam"));
+        assert_eq!(geo.country_code.as_deref(), Some("NL"));
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+        // Answered from the cache
+        assert!(geo_lookup_online("198.51.100.7", &url_template).await.is_some());
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn geo_fallback_cache_is_bounded() {
+        let mut cache = GeoFallbackCache::default();
+        for n in 0..5u8 {
+            cache.insert(std::net::IpAddr::from([198, 51, 100, n]), None, 3);
+        }
+        assert_eq!(cache.entries.len(), 3);
+        assert!(cache.get(&std::net::IpAddr::from([198, 51, 100, 1])).is_none());
+        assert!(matches!(cache.get(&std::net::IpAddr::from([198, 51, 100, 4])), Some(None)));
+    }
+
+    #[tokio::test]
+    async fn slow_load_finishes_in_background() {
+        let (late_tx, late_rx) = tokio::sync::oneshot::channel();
+        let slow_open = tokio::task::spawn_blocking(|| {
+            std::thread::sleep(std::time::Duration::from_millis(300));
+            "db"
+        });
+
+        let started = std::time::Instant::now();
+        let waited = finish_in_background_after(std::time::Duration::from_millis(50), slow_open, move |result| {
+            let _ = late_tx.send(result.unwrap());
+        })
+        .await;
+        assert!(waited.is_none(), "a slow open must not be waited on");
+        assert!(started.elapsed() < std::time::Duration::from_millis(250));
+
+        // Its result still arrives once the open finishes
+        let late = tokio::time::timeout(std::time::Duration::from_secs(5), late_rx).await.unwrap().unwrap();
+        assert_eq!(late, "db");
+
+        let fast_open = tokio::task::spawn_blocking(|| "db");
+        let waited = finish_in_background_after(std::time::Duration::from_secs(5), fast_open, |_| panic!("not late")).await;
+        assert_eq!(waited.unwrap().unwrap(), "db");
+    }
+
+    fn test_state() -> AppState {
+        AppState {
+            running_traces: Arc::new(Mutex::new(HashMap::new())),
+            active_children: Arc::new(AtomicUsize::new(0)),
+            trace_outcomes: Arc::new(Mutex::new(std::collections::VecDeque::new())),
+        }
+    }
+
+    // A running trace that only ends when it is cancelled
+    fn insert_waiting_trace(state: &AppState, trace_id: &str) {
+        let cancel_notify = Arc::new(Notify::new());
+        let notified = cancel_notify.clone();
+        let handle = tokio::spawn(async move {
+            notified.notified().await;
+            Err("Trace cancelled by user".to_string())
+        });
+        state.running_traces.lock().unwrap().insert(
+            trace_id.to_string(),
+            RunningTrace { cancel_notify, cancelled: Arc::new(AtomicBool::new(false)), handle },
+        );
+    }
+
+    #[tokio::test]
+    async fn stop_traces_reports_each_id() {
+        let state = test_state();
+        insert_waiting_trace(&state, "first");
+        insert_waiting_trace(&state, "second");
+
+        let ids = ["first", "unknown", "second"].iter().map(|id| id.to_string()).collect();
+        let results = cancel_traces(&state, ids).await;
+
+        let outcomes: Vec<(&str, bool)> = results.iter().map(|r| (r.trace_id.as_str(), r.stopped)).collect();
+        assert_eq!(outcomes, vec![("first", true), ("unknown", false), ("second", true)]);
+        assert_eq!(results[1].error.as_deref(), Some("Trace not found"));
+        assert!(results[0].error.is_none());
+        assert!(state.running_traces.lock().unwrap().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn hops_carry_when_their_line_was_received() {
+        let before = chrono::Utc::now();
+        let result = execute_trace_with_cancel(
+            None,
+            "sh".to_string(),
+            vec!["-c".to_string(), "echo ' 1  10.0.0.1  1.0 ms'; echo ' 2  10.0.0.2  2.0 ms'".to_string()],
+            TraceOptions { resolve_dns: Some(false), ..Default::default() },
+            Arc::new(Notify::new()),
+            "received-at".to_string(),
+            Arc::new(AtomicUsize::new(0)),
+        )
+        .await
+        .unwrap();
+        let after = chrono::Utc::now();
+
+        assert_eq!(result.hops.len(), 2);
+        for hop in &result.hops {
+            let received_at = chrono::DateTime::parse_from_rfc3339(&hop.received_at)
+                .unwrap_or_else(|e| panic!("hop {} received_at {:?}: {}", hop.hop, hop.received_at, e));
+            assert!(received_at >= before && received_at <= after);
+        }
+    }
+
+    #[tokio::test]
+    async fn private_ipv6_gets_the_private_placeholder() {
+        for ip in ["fe80::1", "fc00::1", "fd12:3456::1"] {
+            let geo = geo_lookup_inner(ip.to_string()).await.unwrap();
+            assert_eq!(geo.city.as_deref(), Some("Private/Internal"), "{}", ip);
+            assert_eq!(geo.lat, None);
+        }
+
+        assert!(!is_private_ipv6(&"2001:4860:4860::8888".parse().unwrap()));
+        assert!(!is_private_ipv6(&"fec0::1".parse().unwrap()));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn probe_ttl_runs_only_that_hop() {
+        let options = probe_ttl_options(7);
+        let (cmd, args) = prepare_trace_command("dns.google", &options).unwrap();
+        assert_eq!(cmd, "traceroute");
+        assert_eq!(args, vec!["-f", "7", "-m", "7", "-n", "--", "dns.google"]);
+
+        // One hop's probes, however far away it is
+        assert_eq!(probe_ttl_timeout(7, &options), probe_ttl_timeout(64, &options));
+        assert_eq!(
+            probe_ttl_timeout(7, &options),
+            tokio::time::Duration::from_millis(DEFAULT_PROBE_TIMEOUT_MS * DEFAULT_PROBES_PER_HOP as u64) + TRACE_IDLE_SLACK
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn probe_ttl_runs_tracert_up_to_the_hop() {
+        let options = probe_ttl_options(7);
+        let (cmd, args) = prepare_trace_command("dns.google", &options).unwrap();
+        assert_eq!(cmd, "tracert");
+        assert_eq!(args, vec!["-d", "-h", "7", "dns.google"]);
+
+        // Every hop up to the TTL may time out
+        let per_hop = tokio::time::Duration::from_millis(DEFAULT_PROBE_TIMEOUT_MS * DEFAULT_PROBES_PER_HOP as u64);
+        assert_eq!(probe_ttl_timeout(7, &options), per_hop * 7 + TRACE_IDLE_SLACK);
+
+        let first_hop = TraceOptions { first_hop: Some(3), ..Default::default() };
+        assert!(prepare_trace_command("dns.google", &first_hop).is_err());
+    }
+
+    #[tokio::test]
+    async fn well_known_addresses_are_labeled() {
+        assert_eq!(well_known_label("8.8.8.8").as_deref(), Some("Google DNS"));
+        assert_eq!(well_known_label("2001:4860:4860:0:0:0:0:8888").as_deref(), Some("Google DNS"));
+        assert_eq!(well_known_label("203.0.113.1"), None);
+
+        let mut hop = HopData { ip: Some("8.8.8.8".to_string()), ..placeholder_hop(10, "success") };
+        enrich_hop(&mut hop, false, None).await;
+        assert_eq!(hop.well_known.as_deref(), Some("Google DNS"));
+
+        // A user table extends and overrides the bundled one, bad entries are skipped
+        let mut labels = HashMap::new();
+        load_well_known_ips(include_str!("../resources/well_known_ips.json"), "bundled", &mut labels);
+        load_well_known_ips(r#"{"8.8.8.8": "Office resolver", "not-an-ip": "x"}"#, "user", &mut labels);
+        assert_eq!(labels.get(&"8.8.8.8".parse().unwrap()).map(String::as_str), Some("Office resolver"));
+        assert_eq!(labels.get(&"1.1.1.1".parse().unwrap()).map(String::as_str), Some("Cloudflare DNS"));
+    }
+
+    #[test]
+    fn control_characters_are_escaped() {
+        let line = " 3  evil\x1b[31m.example\x1b[0m (203.0.113.9)  5.1 ms  5.2 ms  5.3 ms\x07";
+        let escaped = escape_control_chars(line);
+        assert_eq!(escaped, " 3  evil\\x1b[31m.example\\x1b[0m (203.0.113.9)  5.1 ms  5.2 ms  5.3 ms\\x07");
+        assert!(!escaped.contains('\x1b'));
+        assert!(matches!(escape_control_chars("1\t10.0.0.1"), std::borrow::Cow::Borrowed("1\t10.0.0.1")));
+
+        // Host names from the tool and from nslookup reach the UI escaped too
+        let hop = unix_hop(" 3  evil\x1b[31m.example\x1b[0m (203.0.113.9)  5.1 ms  5.2 ms  5.3 ms");
+        assert_eq!(hop.host.as_deref(), Some("evil\\x1b[31m.example\\x1b[0m"));
+        assert_eq!(hop.ip.as_deref(), Some("203.0.113.9"));
+        assert_eq!(hop.latencies.len(), 3);
+
+        let ptr = "9.113.0.203.in-addr.arpa\tname = evil\x1b[2J.example.\n";
+        assert_eq!(parse_nslookup_ptr(ptr).as_deref(), Some("evil\\x1b[2J.example"));
+    }
+
+    #[test]
+    fn parses_windows_ping_output() {
+        let output = "\r\nPinging 8.8.8.8 with 32 bytes of data:\r\nReply from 8.8.8.8: bytes=32 time=14ms TTL=117\r\nRequest timed out.\r\n\r\nPing statistics for 8.8.8.8:\r\n    Packets: Sent = 4, Received = 3, Lost = 1 (25% loss),\r\nApproximate round trip times in milli-seconds:\r\n    Minimum = 14ms, Maximum = 16ms, Average = 15ms\r\n";
+        let stats = parse_ping_output(output).unwrap();
+        assert_eq!((stats.sent, stats.received), (4, 3));
+        assert_eq!((stats.min_ms, stats.avg_ms, stats.max_ms), (Some(14.0), Some(15.0), Some(16.0)));
+
+        // Nothing answered: no round trip times
+        let silent = "Ping statistics for 10.0.0.9:\r\n    Packets: Sent = 2, Received = 0, Lost = 2 (100% loss),\r\n";
+        let stats = parse_ping_output(silent).unwrap();
+        assert_eq!((stats.sent, stats.received), (2, 0));
+        assert_eq!(stats.avg_ms, None);
+    }
+
+    #[test]
+    fn parses_unix_ping_output() {
+        let iputils = "PING 8.8.8.8 (8.8.8.8) 56(84) bytes of data.\n64 bytes from 8.8.8.8: icmp_seq=1 ttl=117 time=14.1 ms\n\n--- 8.8.8.8 ping statistics ---\n4 packets transmitted, 4 received, 0% packet loss, time 3004ms\nrtt min/avg/max/mdev = 14.1/15.2/16.3/0.8 ms\n";
+        let stats = parse_ping_output(iputils).unwrap();
+        assert_eq!((stats.sent, stats.received), (4, 4));
+        assert_eq!((stats.min_ms, stats.avg_ms, stats.max_ms), (Some(14.1), Some(15.2), Some(16.3)));
+
+        let busybox = "--- 8.8.8.8 ping statistics ---\n3 packets transmitted, 2 packets received, 33% packet loss\nround-trip min/avg/max = 9.870/10.123/10.376 ms\n";
+        let stats = parse_ping_output(busybox).unwrap();
+        assert_eq!((stats.sent, stats.received), (3, 2));
+        assert_eq!(stats.avg_ms, Some(10.123));
+
+        let macos = "--- 8.8.8.8 ping statistics ---\n2 packets transmitted, 2 packets received, 0.0% packet loss\nround-trip min/avg/max/stddev = 12.001/12.500/12.999/0.499 ms\n";
+        assert_eq!(parse_ping_output(macos).unwrap().max_ms, Some(12.999));
+
+        assert!(parse_ping_output("ping: unknown host\n").is_none());
+    }
+
+    #[test]
+    fn ping_waits_a_bounded_time_per_reply() {
+        let (_, args) = prepare_ping_command("8.8.8.8".parse().unwrap(), 4);
+        if cfg!(windows) {
+            assert_eq!(args, vec!["-n", "4", "-w", "1000", "8.8.8.8"]);
+        } else if cfg!(target_os = "macos") {
+            assert_eq!(args, vec!["-c", "4", "-W", "1000", "8.8.8.8"]);
+        } else {
+            assert_eq!(args, vec!["-c", "4", "-W", "1", "8.8.8.8"]);
+        }
+        assert_eq!(ping_deadline(4), tokio::time::Duration::from_secs(3 + 1) + PING_DEADLINE_SLACK);
+    }
+
+    #[test]
+    fn latency_precision_keeps_sub_millisecond_values() {
+        assert_eq!(normalize_latency(0.234, DEFAULT_LATENCY_PRECISION), 0.234);
+        assert_eq!(normalize_latency(0.02345, DEFAULT_LATENCY_PRECISION), 0.0235);
+        assert_eq!(normalize_latency(81.0, DEFAULT_LATENCY_PRECISION), 81.0);
+        assert_eq!(normalize_latency(81.26, DEFAULT_LATENCY_PRECISION), 81.3);
+        assert_eq!(normalize_latency(81.26, 0), 81.0);
+        assert_eq!(normalize_latency(0.234, 0), 0.234);
+        assert_eq!(normalize_latency(1.23456789, 99), 1.234568);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn every_trace_leaves_a_stored_outcome() {
+        let state = test_state();
+        let not_cancelled = AtomicBool::new(false);
+
+        let finished = execute_trace_with_cancel(
+            None,
+            "sh".to_string(),
+            vec!["-c".to_string(), "echo ' 1  10.0.0.1  1.0 ms'".to_string()],
+            TraceOptions { resolve_dns: Some(false), ..Default::default() },
+            Arc::new(Notify::new()),
+            "finished".to_string(),
+            state.active_children.clone(),
+        );
+        run_and_record_outcome(finished, &state.trace_outcomes, "finished", &not_cancelled).await.unwrap();
+        let outcome = find_trace_outcome(&state.trace_outcomes, "finished").unwrap();
+        assert_eq!(outcome.status, "completed");
+        assert_eq!(outcome.result.unwrap().hops.len(), 1);
+
+        let failed = async { Err("traceroute: command not found".to_string()) };
+        assert!(run_and_record_outcome(failed, &state.trace_outcomes, "failed", &not_cancelled).await.is_err());
+        let outcome = find_trace_outcome(&state.trace_outcomes, "failed").unwrap();
+        assert_eq!(outcome.status, "failed");
+        assert_eq!(outcome.error.as_deref(), Some("traceroute: command not found"));
+
+        let panicked = async { panic!("parser bug") };
+        assert!(run_and_record_outcome(panicked, &state.trace_outcomes, "panicked", &not_cancelled).await.is_err());
+        let outcome = find_trace_outcome(&state.trace_outcomes, "panicked").unwrap();
+        assert_eq!(outcome.status, "panicked");
+        assert_eq!(outcome.error.as_deref(), Some("Trace panicked: parser bug"));
+
+        // Stopped through cancel_trace, as stop_trace does
+        let cancel_notify = Arc::new(Notify::new());
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let trace_future = execute_trace_with_cancel(
+            None,
+            "sh".to_string(),
+            vec!["-c".to_string(), "exec sleep 999".to_string()],
+            TraceOptions { resolve_dns: Some(false), ..Default::default() },
+            cancel_notify.clone(),
+            "stopped".to_string(),
+            state.active_children.clone(),
+        );
+        let outcomes = state.trace_outcomes.clone();
+        let cancelled_for_task = cancelled.clone();
+        let handle = tokio::spawn(async move {
+            run_and_record_outcome(trace_future, &outcomes, "stopped", &cancelled_for_task).await
+        });
+        state.running_traces.lock().unwrap().insert(
+            "stopped".to_string(),
+            RunningTrace { cancel_notify, cancelled, handle },
+        );
+        while state.active_children.load(Ordering::SeqCst) == 0 {
+            tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+        }
+        cancel_trace(&state, "stopped").await.unwrap();
+        let outcome = find_trace_outcome(&state.trace_outcomes, "stopped").unwrap();
+        assert_eq!(outcome.status, "cancelled");
+
+        assert!(find_trace_outcome(&state.trace_outcomes, "unknown").is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn tool_path_must_be_an_existing_trace_tool() {
+        let tool_options = |path: &str| TraceOptions { tool_path: Some(path.to_string()), ..Default::default() };
+
+        let error = prepare_trace_command("dns.google", &tool_options("/nonexistent/bin/traceroute")).unwrap_err();
+        assert_eq!(error, "Trace tool not found or not a file: /nonexistent/bin/traceroute");
+
+        // Any other program is refused, even one that exists
+        let error = prepare_trace_command("dns.google", &tool_options("/bin/sh")).unwrap_err();
+        assert!(error.starts_with("Trace tool must be named"), "{}", error);
+        assert!(prepare_trace_command("dns.google", &tool_options("bin/traceroute")).is_err());
+        assert!(prepare_trace_command("dns.google", &tool_options("/tmp/traceroute/")).is_err());
+
+        let dir = std::env::temp_dir().join(format!("tool-path-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let tool = dir.join("traceroute");
+        std::fs::write(&tool, "").unwrap();
+        let prepared = prepare_trace_command("dns.google", &tool_options(tool.to_str().unwrap()));
+        std::fs::remove_dir_all(&dir).unwrap();
+        let (cmd, args) = prepared.unwrap();
+        assert_eq!(cmd, tool.to_str().unwrap());
+        assert_eq!(args.last().map(String::as_str), Some("dns.google"));
+
+        // A saved toolPath is checked the same way when the options are restored
+        let saved = serde_json::json!({ "toolPath": "/bin/sh" });
+        assert!(parse_saved_options(saved).is_err());
+    }
+
+    #[test]
+    fn salvages_an_ip_glued_to_another_token() {
+        // The address column is glued to the name, parsing finds no address of its own
+        let hop = unix_hop(" 4  edge-1.example203.0.113.5  12.1 ms  12.4 ms  12.2 ms");
+        assert_eq!(hop.ip.as_deref(), Some("203.0.113.5"));
+        assert_eq!(hop.status, "success");
+        assert_eq!(hop.probe_ips, vec![Some("203.0.113.5".to_string()); 3]);
+
+        let v6 = unix_hop(" 4  [2001:db8::1]  12.1 ms  12.4 ms");
+        assert_eq!(v6.ip.as_deref(), Some("2001:db8::1"));
+
+        // Nothing answered, nothing to salvage
+        let silent = unix_hop(" 5  edge-1.example203.0.113.5 * * *");
+        assert_eq!(silent.ip, None);
+
+        assert_eq!(salvage_ip("no address here 999.1.2.3"), None);
+    }
+
+    #[tokio::test]
+    async fn download_retries_server_errors_only() {
+        let dir = std::env::temp_dir().join(format!("geolite-download-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dest = dir.join("GeoLite2-City.mmdb");
+        let backoff = std::time::Duration::from_millis(10);
+
+        let (addr, hits) = mock_http_server(vec![(503, "busy"), (200, "mmdb bytes")]).await;
+        download_with_backoff(&format!("http://{}/db", addr), &dest, backoff).await.unwrap();
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "mmdb bytes");
+        assert!(!dest.with_extension("part").exists());
+        std::fs::remove_file(&dest).unwrap();
+
+        // 4xx other than 429 is not retried
+        let (addr, hits) = mock_http_server(vec![(404, "")]).await;
+        let error = download_with_backoff(&format!("http://{}/db", addr), &dest, backoff).await.unwrap_err();
+        assert!(error.contains("404"), "{}", error);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        // Gives up after the last attempt, leaving nothing behind
+        let (addr, hits) = mock_http_server(vec![(429, "")]).await;
+        assert!(download_with_backoff(&format!("http://{}/db", addr), &dest, backoff).await.is_err());
+        assert_eq!(hits.load(Ordering::SeqCst), GEOLITE_DOWNLOAD_ATTEMPTS as usize);
+        assert!(!dest.exists());
+        assert!(!dest.with_extension("part").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn stats_window_caps_samples_but_not_counters() {
+        let mut stats = HopStats::default();
+        for iteration in 0..1000 {
+            let rtt = iteration as f64;
+            stats.record(&hop_with_latencies(&[Some(rtt), None, Some(rtt)]), DEFAULT_STATS_WINDOW);
+            assert!(stats.samples.len() <= DEFAULT_STATS_WINDOW);
+        }
+
+        assert_eq!(stats.samples.len(), DEFAULT_STATS_WINDOW);
+        // Lifetime loss still counts every probe
+        assert_eq!(stats.sent, 3000);
+        assert_eq!(stats.received, 2000);
+        assert!((stats.loss_percent() - 100.0 / 3.0).abs() < 1e-9);
+        // Only the newest samples are averaged: the last 150 iterations, 850..=999
+        assert_eq!(stats.avg_ms(), Some(924.5));
+    }
+
+    #[test]
+    fn settler_merges_probes_across_lines() {
+        let mut settler = HopSettler::new(3, DEFAULT_LATENCY_PRECISION);
+
+        let first = unix_hop(" 1  10.0.0.1 (10.0.0.1)  1.0 ms");
+        assert!(settler.start_hop(&first).is_empty());
+
+        let (merged, settled) = settler.continue_hop("    10.0.0.2 (10.0.0.2)  2.0 ms").unwrap();
+        assert!(!settled);
+        assert_eq!(merged.latencies, vec![Some(1.0), Some(2.0)]);
+
+        let (merged, settled) = settler.continue_hop("    10.0.0.2 (10.0.0.2)  3.0 ms").unwrap();
+        assert!(settled);
+        assert_eq!(merged.latencies, vec![Some(1.0), Some(2.0), Some(3.0)]);
+        assert_eq!(merged.avg_latency, Some(2.0));
+        assert_eq!(merged.ip.as_deref(), Some("10.0.0.1"));
+        assert_eq!(
+            merged.probe_ips,
+            vec![Some("10.0.0.1".to_string()), Some("10.0.0.2".to_string()), Some("10.0.0.2".to_string())]
+        );
+
+        // Settled hops take no more continuations, a header line never is one
+        assert!(settler.continue_hop("    10.0.0.9  9.0 ms").is_none());
+        assert!(settler.continue_hop("traceroute to 8.8.8.8 (8.8.8.8), 30 hops max").is_none());
+
+        // An incomplete hop is settled by the next hop line
+        settler.start_hop(&unix_hop(" 2  10.0.0.3  4.0 ms"));
+        let settled = settler.start_hop(&unix_hop(" 3  10.0.0.4  5.0 ms  5.0 ms  5.0 ms"));
+        let settled_hops: Vec<u32> = settled.iter().map(|hop| hop.hop).collect();
+        assert_eq!(settled_hops, vec![2, 3]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn continuation_lines_are_merged_with_or_without_settled_events() {
+        let script = "echo ' 1  10.0.0.1 (10.0.0.1)  1.0 ms'; echo '    10.0.0.2 (10.0.0.2)  2.0 ms  3.0 ms'; echo ' 2  10.0.0.3  4.0 ms  5.0 ms  6.0 ms'";
+        for settled_events in [None, Some(false), Some(true)] {
+            let result = execute_trace_with_cancel(
+                None,
+                "sh".to_string(),
+                vec!["-c".to_string(), script.to_string()],
+                TraceOptions { resolve_dns: Some(false), settled_events, ..Default::default() },
+                Arc::new(Notify::new()),
+                "continuation".to_string(),
+                Arc::new(AtomicUsize::new(0)),
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(result.hops.len(), 2, "settledEvents {:?}", settled_events);
+            assert_eq!(result.hops[0].latencies, vec![Some(1.0), Some(2.0), Some(3.0)], "settledEvents {:?}", settled_events);
+            assert_eq!(result.hops[0].probe_ips[2].as_deref(), Some("10.0.0.2"));
+            assert_eq!(result.hops[1].latencies.len(), 3);
+        }
+    }
+
+    #[test]
+    fn dash_targets_never_become_flags() {
+        for target in ["-h", "--help", "-", "-n8.8.8.8", "--sport=53"] {
+            assert!(!is_valid_target(target), "{}", target);
+        }
+        assert!(is_valid_target("dns.google"));
+        assert!(is_valid_target("a-b.example"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn options_end_before_the_target() {
+        let options = TraceOptions { max_hops: Some(5), resolve_dns: Some(false), ..Default::default() };
+        let (_, args) = prepare_trace_command("dns.google", &options).unwrap();
+        assert_eq!(args[args.len() - 2..], ["--", "dns.google"]);
+        assert_eq!(args.iter().filter(|arg| *arg == "--").count(), 1);
+    }
+
+    #[test]
+    fn hop_status_goes_pending_success_partial() {
+        let mut statuses = HashMap::new();
+        let mut settler = HopSettler::new(3, DEFAULT_LATENCY_PRECISION);
+
+        // First probe answered
+        let first = unix_hop(" 1  10.0.0.1  1.0 ms");
+        settler.start_hop(&first);
+        assert_eq!(
+            hop_status_transition(&mut statuses, &first),
+            Some(("pending".to_string(), "success".to_string()))
+        );
+
+        // A later probe times out on a continuation line
+        let (merged, _) = settler.continue_hop("    *").unwrap();
+        assert_eq!(
+            hop_status_transition(&mut statuses, &merged),
+            Some(("success".to_string(), "partial".to_string()))
+        );
+
+        // No event while the status stays the same
+        let (merged, settled) = settler.continue_hop("    10.0.0.1  1.2 ms").unwrap();
+        assert!(settled);
+        assert_eq!(hop_status_transition(&mut statuses, &merged), None);
+
+        // Hops are tracked separately
+        let silent = unix_hop(" 2  * * *");
+        assert_eq!(
+            hop_status_transition(&mut statuses, &silent),
+            Some(("pending".to_string(), "timeout".to_string()))
+        );
+    }
+
+    const WINDOWS_TRANSCRIPT: &str = "\r\nTracing route to dns.google [8.8.8.8]\r\nover a maximum of 30 hops:\r\n\r\n  1    <1 ms    <1 ms    <1 ms  192.168.1.1\r\n  2     *        *        *     Request timed out.\r\n  3    12 ms    11 ms    13 ms  10.20.0.1\r\n  4    14 ms    15 ms    14 ms  dns.google [8.8.8.8]\r\n\r\nTrace complete.\r\n";
+    const UNIX_TRANSCRIPT: &str = "traceroute to dns.google (8.8.8.8), 30 hops max, 60 byte packets\n 1  _gateway (192.168.1.1)  0.345 ms  0.234 ms  0.198 ms\n 2  * * *\n 3  10.20.0.1 (10.20.0.1)  11.512 ms  11.611 ms  11.702 ms\n 4  dns.google (8.8.8.8)  14.1 ms  14.3 ms  14.2 ms\n";
+
+    #[tokio::test]
+    async fn parses_captured_windows_transcript() {
+        let result = parse_trace_text(WINDOWS_TRANSCRIPT.to_string(), "windows".to_string()).await.unwrap();
+        assert_eq!(result.target, "dns.google");
+        assert_eq!(result.resolved_ip.as_deref(), Some("8.8.8.8"));
+        let hops: Vec<(u32, Option<&str>, &str)> = result.hops.iter()
+            .map(|hop| (hop.hop, hop.ip.as_deref(), hop.status.as_str()))
+            .collect();
+        assert_eq!(hops, vec![
+            (1, Some("192.168.1.1"), "success"),
+            (2, None, "timeout"),
+            (3, Some("10.20.0.1"), "success"),
+            (4, Some("8.8.8.8"), "success"),
+        ]);
+        assert_eq!(result.hops[3].host.as_deref(), Some("dns.google"));
+        assert!(result.reached_destination);
+
+        // Auto-detection reads it the same way
+        let auto = parse_trace_text(WINDOWS_TRANSCRIPT.to_string(), "auto".to_string()).await.unwrap();
+        assert_eq!(auto.hops.len(), 4);
+        assert_eq!(auto.hops[2].latencies, vec![Some(12.0), Some(11.0), Some(13.0)]);
+        assert_eq!(auto.hops[0].latencies, result.hops[0].latencies);
+    }
+
+    #[tokio::test]
+    async fn parses_captured_unix_transcript() {
+        let result = parse_trace_text(UNIX_TRANSCRIPT.to_string(), "unix".to_string()).await.unwrap();
+        assert_eq!(result.target, "dns.google");
+        assert_eq!(result.resolved_ip.as_deref(), Some("8.8.8.8"));
+        assert_eq!(result.hops.len(), 4);
+        assert_eq!(result.hops[0].host.as_deref(), Some("_gateway"));
+        assert_eq!(result.hops[0].avg_latency, Some(0.259));
+        assert_eq!(result.hops[1].status, "timeout");
+        assert_eq!(result.hops[3].ip.as_deref(), Some("8.8.8.8"));
+        assert!(result.reached_destination);
+
+        assert!(parse_trace_text(UNIX_TRANSCRIPT.to_string(), "plan9".to_string()).await.is_err());
+        assert!(parse_trace_text("nothing to see".to_string(), "auto".to_string()).await.is_err());
+    }
+
+    #[test]
+    fn detects_format_from_headers_and_columns() {
+        assert_eq!(detect_format(&["Tracing route to dns.google [8.8.8.8]"]), Platform::Windows);
+        assert_eq!(detect_format(&["over a maximum of 30 hops:"]), Platform::Windows);
+        assert_eq!(detect_format(&["traceroute to dns.google (8.8.8.8), 30 hops max, 60 byte packets"]), Platform::Unix);
+        assert_eq!(detect_format(&["traceroute6 to dns.google (2001:4860:4860::8888), 30 hops max"]), Platform::Unix);
+
+        // The header decides even when it comes after hop lines
+        assert_eq!(
+            detect_format(&["  1    <1 ms    <1 ms    <1 ms  192.168.1.1", "traceroute to x (1.2.3.4)"]),
+            Platform::Unix
+        );
+
+        // Without a header the columns vote
+        assert_eq!(
+            detect_format(&["  1    <1 ms    <1 ms    <1 ms  192.168.1.1", "  2    12 ms    11 ms    13 ms  10.20.0.1"]),
+            Platform::Windows
+        );
+        assert_eq!(detect_format(&["  2     *        *        *     Request timed out."]), Platform::Windows);
+        assert_eq!(
+            detect_format(&[" 1  192.168.1.1 (192.168.1.1)  0.345 ms  0.234 ms  0.198 ms", " 2  * * *"]),
+            Platform::Unix
+        );
+
+        // Nothing to go on
+        assert_eq!(detect_format(&[" 2  * * *"]), NATIVE_PLATFORM);
+        assert_eq!(detect_format(&[]), NATIVE_PLATFORM);
+    }
+
+    #[test]
+    fn path_tree_branches_on_load_balanced_hops() {
+        let result = trace_result(vec![
+            unix_hop(" 1  10.0.0.1  1.0 ms  1.1 ms  1.2 ms"),
+            unix_hop(" 2  10.0.1.1  5.0 ms 10.0.2.1  5.1 ms 10.0.1.1  5.2 ms"),
+            unix_hop(" 3  8.8.8.8  9.0 ms  9.1 ms  9.2 ms"),
+        ]);
+        let tree = trace_to_path_tree(&result);
+        let node = |id: &str| tree.nodes.iter().find(|node| node.id == id).unwrap_or_else(|| panic!("no node {}", id));
+
+        assert_eq!(tree.root, "source");
+        assert_eq!(tree.nodes.len(), 5);
+        assert_eq!(node("source").children, vec!["1:10.0.0.1"]);
+        assert_eq!(node("1:10.0.0.1").children, vec!["2:10.0.1.1", "2:10.0.2.1"]);
+        assert_eq!(node("2:10.0.1.1").probes, 2);
+        assert_eq!(node("2:10.0.2.1").probes, 1);
+        // Both branches join again at the destination
+        assert_eq!(node("2:10.0.1.1").children, vec!["3:8.8.8.8"]);
+        assert_eq!(node("2:10.0.2.1").children, vec!["3:8.8.8.8"]);
+        assert_eq!(node("3:8.8.8.8").probes, 3);
+        assert!(node("3:8.8.8.8").children.is_empty());
+    }
+
+    #[tokio::test]
+    async fn enrichment_concurrency_stays_under_the_cap() {
+        let concurrency = |value: Option<usize>| enrich_concurrency(&TraceOptions { enrich_concurrency: value, ..Default::default() });
+        assert_eq!(concurrency(None), DEFAULT_ENRICH_CONCURRENCY);
+        assert_eq!(concurrency(Some(0)), 1);
+        assert_eq!(concurrency(Some(10)), 10);
+        // An oversized value from the frontend is clamped instead of panicking in Semaphore::new
+        assert_eq!(concurrency(Some(usize::MAX)), MAX_ENRICH_CONCURRENCY);
+        let _ = tokio::sync::Semaphore::new(concurrency(Some(usize::MAX)));
+
+        let cap = 3;
+        let limit = Arc::new(tokio::sync::Semaphore::new(cap));
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let tasks = (0..20).map(|_| {
+            let running = running.clone();
+            let peak = peak.clone();
+            run_limited(limit.clone(), async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(tokio::time::Duration::from_millis(5)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+            })
+        });
+        futures::future::join_all(tasks).await;
+
+        assert_eq!(peak.load(Ordering::SeqCst), cap);
+        assert_eq!(running.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn transcript_keeps_stdout_and_stderr_separable() {
+        let mut transcript = RawTranscript::default();
+        transcript.push(OutputStream::Stdout, "traceroute to 8.8.8.8 (8.8.8.8), 30 hops max");
+        transcript.push(OutputStream::Stderr, "traceroute: warning: multiple interfaces found");
+        transcript.push(OutputStream::Stdout, " 1  192.168.1.1 (192.168.1.1)  1.234 ms");
+        transcript.push(OutputStream::Stderr, "traceroute: sendto: No route to host");
+
+        assert_eq!(
+            transcript.stream(OutputStream::Stdout),
+            "traceroute to 8.8.8.8 (8.8.8.8), 30 hops max\n 1  192.168.1.1 (192.168.1.1)  1.234 ms\n"
+        );
+        assert_eq!(
+            transcript.stream(OutputStream::Stderr),
+            "traceroute: warning: multiple interfaces found\ntraceroute: sendto: No route to host\n"
+        );
+        // The combined view follows read order, not one stream after the other
+        let combined = transcript.combined();
+        let lines: Vec<&str> = combined.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "traceroute to 8.8.8.8 (8.8.8.8), 30 hops max",
+                "traceroute: warning: multiple interfaces found",
+                " 1  192.168.1.1 (192.168.1.1)  1.234 ms",
+                "traceroute: sendto: No route to host",
+            ]
+        );
+    }
+
+    #[test]
+    fn routing_efficiency_from_known_coordinates() {
+        // London to Paris is about 344 km
+        let london_paris = haversine_km(51.5074, -0.1278, 48.8566, 2.3522);
+        assert!((london_paris - 343.5).abs() < 1.0, "got {}", london_paris);
+
+        // Along the equator a quarter turn is 10007.5 km: out to 90E and back to 45E covers three
+        // times the direct distance from 0 to 45E. The unlocated hop in between is skipped.
+        let result = trace_result(vec![
+            located_hop(1, "10.0.0.1", 0.0, 0.0, "Origin"),
+            unix_hop(" 2  203.0.113.2 (203.0.113.2)  20.1 ms"),
+            located_hop(3, "203.0.113.3", 0.0, 90.0, "Detour"),
+            located_hop(4, "8.8.8.8", 0.0, 45.0, "Target"),
+        ]);
+        let efficiency = routing_efficiency(&result).unwrap();
+        assert_eq!(efficiency.located_hops, 3);
+        assert!((efficiency.direct_km - 5003.8).abs() < 0.1, "got {}", efficiency.direct_km);
+        assert!((efficiency.path_km - 15011.3).abs() < 0.1, "got {}", efficiency.path_km);
+        assert!((efficiency.efficiency - 3.0).abs() < 1e-9);
+
+        // Fewer than two located hops gives nothing to compare
+        let single = trace_result(vec![located_hop(1, "10.0.0.1", 0.0, 0.0, "Origin"), unix_hop(" 2  8.8.8.8 (8.8.8.8)  20.1 ms")]);
+        assert!(routing_efficiency(&single).is_none());
+    }
+
+    #[tokio::test]
+    async fn compact_form_is_smaller_and_round_trips() {
+        let mut result = parse_trace_text(UNIX_TRANSCRIPT.to_string(), "unix".to_string()).await.unwrap();
+        result.raw_output = UNIX_TRANSCRIPT.to_string();
+        result.stdout_raw = UNIX_TRANSCRIPT.to_string();
+        result.hops[3].geo = Some(GeoLocation {
+            lat: Some(37.386),
+            lng: Some(-122.0838),
+            city: Some("Mountain View".to_string()),
+            country: Some("United States".to_string()),
+            country_code: Some("US".to_string()),
+            flag_emoji: flag_emoji("US"),
+        });
+
+        let full = serde_json::to_string(&result).unwrap();
+        let compact = serde_json::to_string(&result.to_compact()).unwrap();
+        assert!(compact.len() * 2 < full.len(), "compact {} bytes, full {} bytes", compact.len(), full.len());
+        assert!(!compact.contains("60 byte packets"));
+
+        let restored = serde_json::from_str::<CompactTrace>(&compact).unwrap().into_result();
+        assert_eq!(restored.target, result.target);
+        assert_eq!(restored.resolved_ip, result.resolved_ip);
+        assert_eq!(restored.reached_destination, result.reached_destination);
+        assert_eq!(restored.hops.len(), result.hops.len());
+        for (restored_hop, hop) in restored.hops.iter().zip(&result.hops) {
+            assert_eq!(restored_hop.hop, hop.hop);
+            assert_eq!(restored_hop.host, hop.host);
+            assert_eq!(restored_hop.ip, hop.ip);
+            assert_eq!(restored_hop.latencies, hop.latencies);
+            assert_eq!(restored_hop.avg_latency, hop.avg_latency);
+            assert_eq!(restored_hop.status, hop.status);
+        }
+        let geo = restored.hops[3].geo.as_ref().unwrap();
+        assert_eq!((geo.lat, geo.lng), (Some(37.386), Some(-122.0838)));
+        assert_eq!(geo.city.as_deref(), Some("Mountain View"));
+        assert_eq!(geo.flag_emoji.as_deref(), Some("\u{1F1FA}\u{1F1F8}"));
+        assert!(restored.raw_output.is_empty());
+    }
+
+    #[tokio::test]
+    async fn hop_geo_fires_after_enriching_a_public_hop() {
+        let (addr, _hits) = mock_http_server(vec![
+            (200, r#"{"success":true,"latitude":50.11,"longitude":8.68,"city":"Frankfurt","country":"Germany","country_code":"DE"}"#),
+        ])
+        .await;
+        let url_template = format!("http://{}/{{ip}}", addr);
+
+        let mut public = unix_hop(" 5  198.51.100.42 (198.51.100.42)  20.1 ms");
+        assert!(hop_geo_payload("trace-1", &public).is_none());
+        enrich_hop(&mut public, false, Some(&url_template)).await;
+        let payload = hop_geo_payload("trace-1", &public).expect("hop:geo after enrichment");
+        assert_eq!(payload["trace_id"], "trace-1");
+        assert_eq!(payload["hop"], 5);
+        assert_eq!(payload["geo"]["lat"], 50.11);
+        assert_eq!(payload["geo"]["lng"], 8.68);
+        assert_eq!(payload["geo"]["city"], "Frankfurt");
+
+        // A private hop gets a location label but nothing to put on the map
+        let mut private = unix_hop(" 1  192.168.1.1 (192.168.1.1)  1.234 ms");
+        enrich_hop(&mut private, false, Some(&url_template)).await;
+        assert_eq!(private.geo.as_ref().and_then(|geo| geo.city.as_deref()), Some("Private/Internal"));
+        assert!(hop_geo_payload("trace-1", &private).is_none());
+    }
+
+    #[test]
+    fn last_options_round_trip_through_settings() {
+        let options = TraceOptions {
+            max_hops: Some(20),
+            timeout_ms: Some(1500),
+            probes_per_hop: Some(2),
+            resolve_dns: Some(true),
+            family: Some(IpFamily::V4),
+            ..TraceOptions::default()
+        };
+        let settings = AppSettings {
+            geo_db_path: Some("/data/GeoLite2-City.mmdb".to_string()),
+            last_options: Some(serde_json::to_value(&options).unwrap()),
+            ..AppSettings::default()
+        };
+        let content = serde_json::to_string_pretty(&settings).unwrap();
+
+        let restored: AppSettings = serde_json::from_str(&content).unwrap();
+        let loaded = last_options_or_default(restored.last_options);
+        assert_eq!(loaded.max_hops, Some(20));
+        assert_eq!(loaded.timeout_ms, Some(1500));
+        assert_eq!(loaded.probes_per_hop, Some(2));
+        assert_eq!(loaded.resolve_dns, Some(true));
+        assert_eq!(loaded.family, Some(IpFamily::V4));
+        assert_eq!(loaded.source_port, None);
+    }
+
+    #[test]
+    fn corrupt_last_options_fall_back_to_defaults() {
+        // A bad entry loses only the options, the rest of the settings file still loads
+        let content = r#"{"geo_db_path":"/data/GeoLite2-City.mmdb","last_options":"not options"}"#;
+        let settings: AppSettings = serde_json::from_str(content).unwrap();
+        assert_eq!(settings.geo_db_path.as_deref(), Some("/data/GeoLite2-City.mmdb"));
+        assert!(parse_saved_options(settings.last_options.clone().unwrap()).is_err());
+        assert!(last_options_or_default(settings.last_options).max_hops.is_none());
+
+        // Wrong types and values a trace would reject are both treated as corruption
+        for saved in [
+            serde_json::json!({ "maxHops": "thirty" }),
+            serde_json::json!({ "maxHops": 30, "sourcePort": 0 }),
+            serde_json::json!({ "maxHops": 30, "family": "ipx" }),
+        ] {
+            assert!(parse_saved_options(saved.clone()).is_err(), "{}", saved);
+            assert!(last_options_or_default(Some(saved)).max_hops.is_none());
+        }
+
+        assert!(last_options_or_default(None).max_hops.is_none());
+    }
+
+    #[test]
+    fn flag_emoji_from_country_code() {
+        assert_eq!(flag_emoji("US").as_deref(), Some("\u{1F1FA}\u{1F1F8}"));
+        assert_eq!(flag_emoji("US").as_deref(), Some("🇺🇸"));
+        assert_eq!(flag_emoji("de").as_deref(), Some("🇩🇪"));
+        for invalid in ["", "U", "USA", "U1", "--", "ÜS"] {
+            assert_eq!(flag_emoji(invalid), None, "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn unique_networks_counts_two_slash_24s() {
+        let result = trace_result(vec![
+            unix_hop(" 1  203.0.113.1 (203.0.113.1)  1.234 ms"),
+            placeholder_hop(2, "timeout"),
+            unix_hop(" 3  203.0.113.77 (203.0.113.77)  5.1 ms"),
+            unix_hop(" 4  198.51.100.5 (198.51.100.5)  9.8 ms"),
+            unix_hop(" 5  198.51.100.200 (198.51.100.200)  10.2 ms"),
+        ]);
+        assert_eq!(unique_networks(&result), vec!["203.0.113.0/24", "198.51.100.0/24"]);
+
+        let v6 = trace_result(vec![unix_hop(" 1  2001:db8:1:2::1 (2001:db8:1:2::1)  1.0 ms")]);
+        assert_eq!(unique_networks(&v6), vec!["2001:db8:1::/48"]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn heartbeats_fire_during_a_slow_trace() {
+        // A tool that prints nothing for a while, the beats must keep coming regardless
+        let mut child = tokio::process::Command::new("sh")
+            .args(["-c", "sleep 0.5"])
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
+        let mut heartbeat = TraceHeartbeat::new(tokio::time::Duration::from_millis(100));
+        let mut beats = Vec::new();
+        loop {
+            tokio::select! {
+                status = child.wait() => {
+                    assert!(status.unwrap().success());
+                    break;
+                }
+                elapsed = heartbeat.tick() => beats.push(elapsed),
+            }
+        }
+
+        assert!(beats.len() >= 3, "only {} heartbeats", beats.len());
+        assert!(beats[0] >= std::time::Duration::from_millis(100));
+        assert!(beats.windows(2).all(|pair| pair[1] > pair[0]));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn heartbeats_span_continuous_runs() {
+        let started = std::time::Instant::now();
+        let mut session = TraceSession::new("continuous-heartbeat".to_string());
+        session.heartbeat = TraceHeartbeat::new(tokio::time::Duration::from_millis(50));
+        let cancel_notify = Notify::new();
+
+        run_in_session(&mut session, "echo ' 1  10.0.0.1  1.0 ms'; sleep 0.3; echo ' 2  10.0.0.2  2.0 ms'").await;
+        let after_first = session.heartbeat.beats;
+        assert!(after_first >= 3, "only {} heartbeats in the first run", after_first);
+
+        // Still beating while continuous mode waits for the next run
+        let interval = tokio::time::Duration::from_millis(300);
+        assert!(pause_between_runs(None, &mut session, interval, &cancel_notify).await);
+        let after_pause = session.heartbeat.beats;
+        assert!(after_pause >= after_first + 3, "only {} heartbeats in the pause", after_pause - after_first);
+
+        run_in_session(&mut session, "sleep 0.3; echo ' 1  10.0.0.1  1.0 ms'").await;
+        assert!(session.heartbeat.beats >= after_pause + 3);
+
+        // Elapsed time counts from the start of the trace, not of the last run
+        let elapsed = session.heartbeat.tick().await;
+        assert!(elapsed >= std::time::Duration::from_millis(900), "{:?}", elapsed);
+        assert!(elapsed <= started.elapsed());
+        assert_eq!(session.hop_statuses.len(), 2);
+
+        // A stop during the pause ends it straight away
+        cancel_notify.notify_one();
+        assert!(!pause_between_runs(None, &mut session, tokio::time::Duration::from_secs(60), &cancel_notify).await);
+    }
+
+    #[tokio::test]
+    async fn measure_dns_times_localhost() {
+        let timing = measure_dns("localhost".to_string()).await.unwrap();
+        assert_eq!(timing.host, "localhost");
+        assert!(!timing.addresses.is_empty());
+        assert!(timing.resolution_ms.is_finite() && timing.resolution_ms >= 0.0, "{}", timing.resolution_ms);
+    }
+
+    #[tokio::test]
+    async fn measure_dns_rejects_invalid_hosts() {
+        assert!(measure_dns(String::new()).await.is_err());
+        assert!(measure_dns("bad host;rm".to_string()).await.is_err());
+        // .invalid is reserved and never resolves
+        assert!(measure_dns("tracert-test.invalid".to_string()).await.is_err());
+    }
+
+    #[test]
+    fn quiet_suppresses_trace_lines() {
+        assert!(should_emit_line(&TraceOptions::default()));
+        assert!(should_emit_line(&TraceOptions { quiet: Some(false), ..Default::default() }));
+        assert!(!should_emit_line(&TraceOptions { quiet: Some(true), ..Default::default() }));
+    }
+
+    #[test]
+    fn geo_db_path_round_trips_through_settings() {
+        let dir = std::env::temp_dir().join(format!("geo-db-settings-{}", std::process::id()));
+        let path = dir.join("TraceRT").join("settings.json");
+
+        // Nothing saved yet
+        assert!(load_settings_from(&path).geo_db_path.is_none());
+
+        let settings = AppSettings {
+            geo_db_path: Some("/data/GeoLite2-City.mmdb".to_string()),
+            ..AppSettings::default()
+        };
+        save_settings_to(&path, &settings).unwrap();
+        let loaded = load_settings_from(&path);
+
+        // A broken file loses the settings, not the app
+        std::fs::write(&path, "{ not json").unwrap();
+        let broken = load_settings_from(&path);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(loaded.geo_db_path.as_deref(), Some("/data/GeoLite2-City.mmdb"));
+        assert!(broken.geo_db_path.is_none());
+    }
+
+    #[test]
+    fn configured_geo_db_is_tried_first() {
+        let app_data_dir = Path::new("/data/TraceRT");
+        let candidates = geo_db_candidates(app_data_dir, Some("/mnt/geo/custom.mmdb".to_string()));
+        assert_eq!(candidates, vec![
+            std::path::PathBuf::from("/mnt/geo/custom.mmdb"),
+            app_data_dir.join("resources").join("GeoLite2-City.mmdb"),
+            app_data_dir.join("GeoLite2-City.mmdb"),
+        ]);
+
+        let candidates = geo_db_candidates(app_data_dir, None);
+        assert_eq!(candidates[0], app_data_dir.join("resources").join("GeoLite2-City.mmdb"));
+        assert_eq!(candidates.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn set_geo_db_path_rejects_missing_files() {
+        let error = set_geo_db_path("/nonexistent/GeoLite2-City.mmdb".to_string()).await.unwrap_err();
+        assert!(error.contains("not found"), "{}", error);
+
+        // A directory is not a database either
+        let dir = std::env::temp_dir().to_string_lossy().into_owned();
+        assert!(set_geo_db_path(dir).await.is_err());
+    }
+
+    #[test]
+    fn settle_timeout_saturates_on_huge_timeouts() {
+        let options = TraceOptions { timeout_ms: Some(2000), probes_per_hop: Some(3), ..Default::default() };
+        let probes = expected_probes(&options) as u64;
+        assert_eq!(hop_settle_timeout(&options), tokio::time::Duration::from_millis(2000 * probes + 1000));
+
+        let huge = TraceOptions { timeout_ms: Some(u64::MAX), probes_per_hop: Some(u32::MAX), ..Default::default() };
+        assert_eq!(hop_settle_timeout(&huge), MAX_TRACE_WAIT);
+        assert_eq!(trace_idle_timeout(&huge), MAX_TRACE_WAIT);
+        // The deadline resets in the read loop must not overflow
+        let _ = tokio::time::Instant::now() + hop_settle_timeout(&huge);
+    }
+
+    // One run of a fake trace tool printing `script`, as a continuous trace would do it
+    #[cfg(unix)]
+    async fn run_in_session(session: &mut TraceSession, script: &str) -> TraceResult {
+        execute_trace_run(
+            None,
+            "sh".to_string(),
+            vec!["-c".to_string(), script.to_string()],
+            TraceOptions { resolve_dns: Some(false), ..Default::default() },
+            Arc::new(Notify::new()),
+            Arc::new(AtomicUsize::new(0)),
+            session,
+        )
+        .await
+        .unwrap()
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn hop_statuses_carry_across_continuous_runs() {
+        let mut session = TraceSession::new("continuous-status".to_string());
+        run_in_session(&mut session, "echo ' 1  10.0.0.1  1.0 ms'; echo ' 2  * * *'").await;
+        let mut statuses: Vec<(u32, String)> = session.hop_statuses.clone().into_iter().collect();
+        statuses.sort();
+        assert_eq!(statuses, vec![(1, "success".to_string()), (2, "timeout".to_string())]);
+
+        // The second run starts from what the UI saw last, hop 1 has nothing new to report
+        assert_eq!(hop_status_transition(&mut session.hop_statuses, &unix_hop(" 1  10.0.0.1  1.1 ms")), None);
+        run_in_session(&mut session, "echo ' 1  10.0.0.1  1.1 ms'; echo ' 2  10.0.0.2  2.0 ms'").await;
+        assert_eq!(session.hop_statuses.get(&1).map(String::as_str), Some("success"));
+        assert_eq!(session.hop_statuses.get(&2).map(String::as_str), Some("success"));
+        assert_eq!(hop_status_transition(&mut session.hop_statuses, &unix_hop(" 2  10.0.0.2  2.1 ms")), None);
+    }
+
+    #[tokio::test]
+    async fn init_geo_db_settles_the_load_status() {
+        let status = init_geo_db().await;
+        assert!(
+            matches!(status, GeoDbLoadStatus::Loaded | GeoDbLoadStatus::NotFound),
+            "{:?}", status
+        );
+        assert_eq!(*GEO_DB_STATUS.read().unwrap(), status);
+        assert_eq!(current_geo_db().is_some(), status == GeoDbLoadStatus::Loaded);
+        assert!(!GEO_DB_LATE_LOAD.load(Ordering::SeqCst));
+
+        // Asking again reports the same, without a second read going astray
+        assert_eq!(init_geo_db().await, status);
+    }
+
+    #[tokio::test]
+    async fn late_geo_db_load_never_replaces_a_set_database() {
+        let failed = tokio::task::spawn_blocking(|| -> Option<&str> { panic!("unreadable database") }).await;
+
+        // set_geo_db_path got there first, the late result is dropped either way
+        let slot = RwLock::new(Some("set by the user"));
+        assert_eq!(install_late_load(&slot, Ok(Some("late load"))), None);
+        assert_eq!(install_late_load(&slot, Ok(None)), None);
+        assert_eq!(*slot.read().unwrap(), Some("set by the user"));
+
+        let slot = RwLock::new(None);
+        assert_eq!(install_late_load(&slot, Ok(None)), Some(GeoDbLoadStatus::NotFound));
+        assert_eq!(install_late_load(&slot, failed), Some(GeoDbLoadStatus::Failed));
+        assert_eq!(install_late_load(&slot, Ok(Some("late load"))), Some(GeoDbLoadStatus::Loaded));
+        assert_eq!(*slot.read().unwrap(), Some("late load"));
+    }
+
+    fn source_port_options(port: u16) -> TraceOptions {
+        TraceOptions { source_port: Some(port), ..Default::default() }
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    #[test]
+    fn source_port_maps_to_sport() {
+        let (cmd, args) = prepare_trace_command("dns.google", &source_port_options(40000)).unwrap();
+        assert_eq!(cmd, "traceroute");
+        let sport = args.iter().position(|arg| arg == "--sport=40000").expect("--sport");
+        let end_of_options = args.iter().position(|arg| arg == "--").unwrap();
+        assert!(sport < end_of_options);
+        assert_eq!(args[end_of_options + 1..], ["dns.google"]);
+
+        let (_, args) = prepare_trace_command("dns.google", &TraceOptions::default()).unwrap();
+        assert!(!args.iter().any(|arg| arg.starts_with("--sport")));
+    }
+
+    #[test]
+    fn source_port_zero_is_rejected() {
+        let error = prepare_trace_command("dns.google", &source_port_options(0)).unwrap_err();
+        assert_eq!(error, "Source port must be between 1 and 65535");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn tracert_rejects_a_source_port() {
+        let error = prepare_trace_command("dns.google", &source_port_options(40000)).unwrap_err();
+        assert_eq!(error, "tracert cannot set a source port");
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn macos_traceroute_rejects_a_source_port() {
+        let error = prepare_trace_command("dns.google", &source_port_options(40000)).unwrap_err();
+        assert_eq!(error, "macOS traceroute cannot set a source port");
+    }
+
+    #[test]
+    fn idle_timeout_covers_a_silent_hop() {
+        let slow = TraceOptions {
+            timeout_ms: Some(20_000),
+            probes_per_hop: Some(3),
+            resolve_dns: Some(false),
+            ..Default::default()
+        };
+        let silent_hop = tokio::time::Duration::from_millis(20_000 * expected_probes(&slow) as u64);
+        assert!(trace_idle_timeout(&slow) > silent_hop);
+
+        let resolving = TraceOptions { resolve_dns: Some(true), ..slow.clone() };
+        assert!(trace_idle_timeout(&resolving) > trace_idle_timeout(&slow));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn silent_trace_tool_is_killed() {
+        let options = TraceOptions {
+            timeout_ms: Some(100),
+            probes_per_hop: Some(1),
+            resolve_dns: Some(false),
+            ..Default::default()
+        };
+        let active_children = Arc::new(AtomicUsize::new(0));
+        let started = std::time::Instant::now();
+        let result = execute_trace_with_cancel(
+            None,
+            "sh".to_string(),
+            vec!["-c".to_string(), "echo 1; exec sleep 999".to_string()],
+            options.clone(),
+            Arc::new(Notify::new()),
+            "idle-test".to_string(),
+            active_children.clone(),
+        )
+        .await;
+
+        let error = result.expect_err("a silent tool must not be waited on forever");
+        assert!(error.contains("no output"), "{}", error);
+        assert!(started.elapsed() < trace_idle_timeout(&options) + tokio::time::Duration::from_secs(5));
+        assert_eq!(active_children.load(Ordering::SeqCst), 0);
+
+        // The child was killed and reaped, not left running
+        let pid = error.rsplit("pid=").next().unwrap().trim();
+        assert!(!process_alive(pid), "child {} is still running", pid);
+    }
+
+    #[cfg(unix)]
+    fn process_alive(pid: &str) -> bool {
+        std::process::Command::new("kill")
+            .args(["-0", pid])
+            .stderr(Stdio::null())
+            .status()
+            .unwrap()
+            .success()
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn stopped_trace_leaves_no_orphan() {
+        let state = test_state();
+        let pid_file = std::env::temp_dir().join(format!("stop-trace-{}.pid", std::process::id()));
+        let cancel_notify = Arc::new(Notify::new());
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let handle = tokio::spawn(execute_trace_with_cancel(
+            None,
+            "sh".to_string(),
+            vec!["-c".to_string(), format!("echo $$ > {}; exec sleep 999", pid_file.display())],
+            TraceOptions { resolve_dns: Some(false), ..Default::default() },
+            cancel_notify.clone(),
+            "orphan-test".to_string(),
+            state.active_children.clone(),
+        ));
+        state.running_traces.lock().unwrap().insert(
+            "orphan-test".to_string(),
+            RunningTrace { cancel_notify, cancelled, handle },
+        );
+
+        let started = std::time::Instant::now();
+        let pid = loop {
+            match std::fs::read_to_string(&pid_file) {
+                Ok(pid) if pid.ends_with('\n') => break pid.trim().to_string(),
+                _ => {
+                    assert!(started.elapsed() < std::time::Duration::from_secs(5), "tool never started");
+                    tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+                }
+            }
+        };
+        std::fs::remove_file(&pid_file).unwrap();
+        assert!(process_alive(&pid));
+
+        cancel_trace(&state, "orphan-test").await.unwrap();
+
+        // By the time stop returns the child is killed and reaped
+        assert!(!process_alive(&pid), "child {} is still running", pid);
+        assert_eq!(state.active_children.load(Ordering::SeqCst), 0);
+    }
 }